@@ -0,0 +1,512 @@
+use crate::decompress::GzDecoder;
+use crate::inflate::Inflater;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Kind {
+    /// Recognises an archive by its file name.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.as_bytes().to_ascii_lowercase();
+        if name.ends_with(b".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(b".tar.gz") || name.ends_with(b".tgz") {
+            Some(Kind::TarGz)
+        } else if name.ends_with(b".zip") {
+            Some(Kind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// The name a member is reported under: `archive:member`.
+pub fn member_display_name(archive: &Path, member: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_owned();
+    name.push(":");
+    name.push(member);
+    PathBuf::from(name)
+}
+
+/// Calls `visit` with the path and contents of every regular file in the
+/// archive at `path`. Members that cannot be read, such as encrypted zip
+/// entries, are passed as errors so the rest of the archive is still
+/// searched; an error affecting the whole archive is returned instead.
+pub fn for_each_member<F>(path: &Path, kind: Kind, mut visit: F) -> io::Result<()>
+where
+    F: FnMut(&Path, io::Result<&mut dyn BufRead>),
+{
+    let file = fs::File::open(path)?;
+    match kind {
+        Kind::Tar => for_each_tar_member(BufReader::new(file), &mut visit),
+        Kind::TarGz => for_each_tar_member(GzDecoder::new(BufReader::new(file)), &mut visit),
+        Kind::Zip => for_each_zip_member(file, &mut visit),
+    }
+}
+
+fn invalid_archive(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The bytes of a NUL padded header field.
+fn field(header: &[u8]) -> &[u8] {
+    let end = header.iter().position(|b| *b == 0).unwrap_or(header.len());
+    &header[..end]
+}
+
+/// Parses a numeric header field, octal or GNU base-256.
+fn tar_number(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        let mut value = (field[0] & 0x7f) as u64;
+        for byte in &field[1..] {
+            value = value
+                .checked_mul(256)
+                .and_then(|v| v.checked_add(*byte as u64))
+                .ok_or_else(|| invalid_archive("tar size field out of range"))?;
+        }
+        return Ok(value);
+    }
+    let text = field
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect::<String>();
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| invalid_archive("invalid tar header"))
+}
+
+/// Reads `len` bytes of entry data, such as a long name, into memory.
+fn read_entry_data<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(data)
+}
+
+/// Finds the `path` record of a PAX extended header.
+fn pax_path(data: &[u8]) -> Option<Vec<u8>> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ')?;
+        let len = std::str::from_utf8(&rest[..space])
+            .ok()?
+            .parse::<usize>()
+            .ok()?;
+        if len <= space || len > rest.len() {
+            return None;
+        }
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            return Some(value.to_vec());
+        }
+        rest = &rest[len..];
+    }
+    None
+}
+
+fn for_each_tar_member<R, F>(mut reader: R, visit: &mut F) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(&Path, io::Result<&mut dyn BufRead>),
+{
+    let mut header = [0u8; 512];
+    // A name set by a preceding GNU long name or PAX header.
+    let mut long_name: Option<Vec<u8>> = None;
+    loop {
+        if let Err(e) = reader.read_exact(&mut header) {
+            // Some writers stop right after the last member.
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                return Ok(());
+            }
+            return Err(e);
+        }
+        if header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        let checksum = tar_number(&header[148..156])?;
+        let sum = header
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    *b as u64
+                }
+            })
+            .sum::<u64>();
+        if checksum != sum {
+            return Err(invalid_archive("invalid tar header checksum"));
+        }
+        let size = tar_number(&header[124..136])?;
+        let padding = (512 - size % 512) % 512;
+        match header[156] {
+            b'L' => {
+                long_name = Some(field(&read_entry_data(&mut reader, size)?).to_vec());
+            }
+            b'x' => {
+                let data = read_entry_data(&mut reader, size)?;
+                if let Some(path) = pax_path(&data) {
+                    long_name = Some(path);
+                }
+            }
+            b'0' | 0 | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = field(&header[..100]);
+                    let prefix = field(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        [prefix, b"/", name].concat()
+                    } else {
+                        name.to_vec()
+                    }
+                });
+                let name = PathBuf::from(OsString::from_vec(name));
+                let mut member = BufReader::new((&mut reader).take(size));
+                visit(&name, Ok(&mut member));
+                // Skip whatever the visitor did not read.
+                io::copy(&mut member, &mut io::sink())?;
+                if member.into_inner().limit() > 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+            }
+            _ => {
+                long_name = None;
+                io::copy(&mut (&mut reader).take(size), &mut io::sink())?;
+            }
+        }
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+}
+
+fn le16(data: &[u8], at: usize) -> u64 {
+    u16::from_le_bytes([data[at], data[at + 1]]) as u64
+}
+
+fn le32(data: &[u8], at: usize) -> u64 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as u64
+}
+
+/// Locates and reads the central directory from the end of a zip file.
+fn read_zip_directory(file: &mut fs::File) -> io::Result<Vec<u8>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    // The end record is 22 bytes plus a comment of up to 64KiB.
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    file.take(tail_len).read_to_end(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|i| tail[*i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid_archive("zip end of central directory not found"))?;
+    let size = le32(&tail, end + 12);
+    let offset = le32(&tail, end + 16);
+    if size == 0xffff_ffff || offset == 0xffff_ffff {
+        return Err(invalid_archive("zip64 archives are not supported"));
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    read_entry_data(file, size)
+}
+
+fn for_each_zip_member<F>(mut file: fs::File, visit: &mut F) -> io::Result<()>
+where
+    F: FnMut(&Path, io::Result<&mut dyn BufRead>),
+{
+    let directory = read_zip_directory(&mut file)?;
+    let mut pos = 0;
+    while pos + 46 <= directory.len() && directory[pos..].starts_with(b"PK\x01\x02") {
+        let entry = &directory[pos..];
+        let flags = le16(entry, 8);
+        let method = le16(entry, 10);
+        let compressed_size = le32(entry, 20);
+        let name_len = le16(entry, 28) as usize;
+        let extra_len = le16(entry, 30) as usize;
+        let comment_len = le16(entry, 32) as usize;
+        let offset = le32(entry, 42);
+        if 46 + name_len > entry.len() {
+            return Err(invalid_archive("truncated zip central directory"));
+        }
+        let name = OsStr::from_bytes(&entry[46..46 + name_len]);
+        let name = Path::new(name);
+        pos += 46 + name_len + extra_len + comment_len;
+        if entry[46..46 + name_len].ends_with(b"/") {
+            continue;
+        }
+        if flags & 1 != 0 {
+            visit(
+                name,
+                Err(invalid_archive("encrypted zip entries are not supported")),
+            );
+            continue;
+        }
+        let mut local = [0u8; 30];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut local)?;
+        if !local.starts_with(b"PK\x03\x04") {
+            return Err(invalid_archive("invalid zip local header"));
+        }
+        let data_offset = offset + 30 + le16(&local, 26) + le16(&local, 28);
+        file.seek(SeekFrom::Start(data_offset))?;
+        let data = (&mut file).take(compressed_size);
+        match method {
+            0 => visit(name, Ok(&mut BufReader::new(data))),
+            8 => visit(name, Ok(&mut BufReader::new(Inflater::new(data)))),
+            _ => visit(
+                name,
+                Err(invalid_archive(&format!(
+                    "unsupported zip compression method {}",
+                    method
+                ))),
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Decision, DeviceMode, FileFilter};
+    use crate::glob::GlobSet;
+
+    fn tar_header(name: &str, typeflag: u8, size: usize) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum = header.iter().map(|b| *b as u32).sum::<u32>();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+
+    fn tar_entry(tar: &mut Vec<u8>, name: &str, typeflag: u8, data: &[u8]) {
+        tar.extend(tar_header(name, typeflag, data.len()));
+        tar.extend(data);
+        tar.resize(tar.len().div_ceil(512) * 512, 0);
+    }
+
+    fn tar_members(tar: &[u8]) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut members = Vec::new();
+        for_each_tar_member(
+            tar,
+            &mut |name: &Path, reader: io::Result<&mut dyn BufRead>| {
+                let mut contents = String::new();
+                reader.unwrap().read_to_string(&mut contents).unwrap();
+                members.push((name.to_path_buf(), contents));
+            },
+        )?;
+        Ok(members)
+    }
+
+    /// A zip archive of `(name, flags, method, data)` entries. Every local
+    /// header carries an extra field that the central directory does not,
+    /// as some writers do.
+    fn zip_archive(entries: &[(&str, u16, u16, &[u8])]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+        for (name, flags, method, data) in entries {
+            let offset = zip.len() as u32;
+            let mut common = Vec::new();
+            common.extend(flags.to_le_bytes());
+            common.extend(method.to_le_bytes());
+            common.extend([0u8; 8]);
+            common.extend((data.len() as u32).to_le_bytes());
+            common.extend([0u8; 4]);
+            common.extend((name.len() as u16).to_le_bytes());
+            zip.extend(b"PK\x03\x04\x14\x00");
+            zip.extend(&common);
+            zip.extend(4u16.to_le_bytes());
+            zip.extend(name.as_bytes());
+            zip.extend([0u8; 4]);
+            zip.extend(*data);
+            directory.extend(b"PK\x01\x02\x14\x00\x14\x00");
+            directory.extend(&common);
+            directory.extend([0u8; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let offset = zip.len() as u32;
+        zip.extend(&directory);
+        zip.extend(b"PK\x05\x06\x00\x00\x00\x00");
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((directory.len() as u32).to_le_bytes());
+        zip.extend(offset.to_le_bytes());
+        zip.extend([0u8; 2]);
+        zip
+    }
+
+    /// Writes `zip` to a file called `name` in the temporary directory.
+    fn write_zip(name: &str, zip: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mygrep-{}-{}", std::process::id(), name));
+        fs::write(&path, zip).unwrap();
+        path
+    }
+
+    /// The members of the zip file at `path`, each with its contents or the
+    /// error reading it.
+    fn zip_members(path: &Path) -> io::Result<Vec<(PathBuf, Result<String, String>)>> {
+        let mut members = Vec::new();
+        for_each_member(
+            path,
+            Kind::Zip,
+            |name: &Path, reader: io::Result<&mut dyn BufRead>| {
+                let contents = reader.map_err(|e| e.to_string()).map(|reader| {
+                    let mut contents = String::new();
+                    reader.read_to_string(&mut contents).unwrap();
+                    contents
+                });
+                members.push((name.to_path_buf(), contents));
+            },
+        )?;
+        Ok(members)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Kind::detect(Path::new("a/b.tar")), Some(Kind::Tar));
+        assert_eq!(Kind::detect(Path::new("b.TAR.GZ")), Some(Kind::TarGz));
+        assert_eq!(Kind::detect(Path::new("b.tgz")), Some(Kind::TarGz));
+        assert_eq!(Kind::detect(Path::new("b.zip")), Some(Kind::Zip));
+        assert_eq!(Kind::detect(Path::new("b.gz")), None);
+        assert_eq!(Kind::detect(Path::new("tar")), None);
+    }
+
+    #[test]
+    fn test_tar_members() {
+        let mut tar = Vec::new();
+        tar_entry(&mut tar, "dir/", b'5', b"");
+        tar_entry(&mut tar, "dir/a.txt", b'0', b"secret=1\n");
+        tar_entry(
+            &mut tar,
+            "././@LongLink",
+            b'L',
+            b"dir/a/very/long/name.txt\0",
+        );
+        tar_entry(&mut tar, "dir/a/very/long/n", b'0', &[b'x'; 600]);
+        tar_entry(&mut tar, "link", b'2', b"");
+        tar.extend([0u8; 1024]);
+        let members = tar_members(&tar).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(
+            members[0],
+            (PathBuf::from("dir/a.txt"), "secret=1\n".into())
+        );
+        assert_eq!(members[1].0, PathBuf::from("dir/a/very/long/name.txt"));
+        assert_eq!(members[1].1.len(), 600);
+    }
+
+    #[test]
+    fn test_tar_bad_checksum() {
+        let mut tar = Vec::new();
+        tar_entry(&mut tar, "a.txt", b'0', b"hello\n");
+        tar[0] = b'b';
+        assert!(tar_members(&tar).is_err());
+    }
+
+    #[test]
+    fn test_pax_path() {
+        let data = b"20 ctime=1234567890\n26 path=some/long/path.rs\n";
+        assert_eq!(pax_path(data), Some(b"some/long/path.rs".to_vec()));
+        assert_eq!(pax_path(b"12 uid=1000\n"), None);
+        assert_eq!(pax_path(b"garbage"), None);
+    }
+
+    #[test]
+    fn test_zip_members() {
+        // "hello hello hello\n", compressed with fixed Huffman codes.
+        let deflated = [
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00,
+        ];
+        let zip = zip_archive(&[
+            ("src/", 0, 0, b""),
+            ("src/lib.rs", 0, 0, b"pub mod a;\n"),
+            ("src/a/main.rs", 0, 8, &deflated),
+            ("secret.txt", 1, 8, b"xxxx"),
+            ("README.bz2", 0, 12, b"BZh9"),
+        ]);
+        let path = write_zip("members.zip", &zip);
+        let members = zip_members(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(members.len(), 4);
+        assert_eq!(
+            members[0],
+            (PathBuf::from("src/lib.rs"), Ok("pub mod a;\n".into()))
+        );
+        assert_eq!(
+            members[1],
+            (
+                PathBuf::from("src/a/main.rs"),
+                Ok("hello hello hello\n".into())
+            )
+        );
+        assert_eq!(
+            members[2],
+            (
+                PathBuf::from("secret.txt"),
+                Err("encrypted zip entries are not supported".into())
+            )
+        );
+        assert_eq!(
+            members[3],
+            (
+                PathBuf::from("README.bz2"),
+                Err("unsupported zip compression method 12".into())
+            )
+        );
+    }
+
+    #[test]
+    fn test_zip_not_an_archive() {
+        let path = write_zip("short.zip", b"PK\x03\x04 but cut short");
+        assert!(zip_members(&path).is_err());
+        let mut zip = zip_archive(&[("a.txt", 0, 0, b"a\n")]);
+        let len = zip.len();
+        zip[len - 6] = 0xff;
+        fs::write(&path, &zip).unwrap();
+        assert!(zip_members(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_zip_include() {
+        let zip = zip_archive(&[
+            ("src/lib.rs", 0, 0, b""),
+            ("src/a/main.rs", 0, 0, b""),
+            ("docs/lib.rs", 0, 0, b""),
+            ("README.md", 0, 0, b""),
+        ]);
+        let include = GlobSet::new(&["src/**/*.rs"]).unwrap();
+        let filter = FileFilter::new(Some(include), None, DeviceMode::Skip, true);
+        let path = write_zip("code.zip", &zip);
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(filter.check(&path, &path, &metadata), Decision::Search);
+        let members = zip_members(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let searched = members
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| filter.check_member(name) == Decision::Search)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            searched,
+            [PathBuf::from("src/lib.rs"), PathBuf::from("src/a/main.rs")]
+        );
+    }
+}
@@ -0,0 +1,599 @@
+use crate::error::MygrepError;
+use crate::filter::{DeviceMode, FileFilter};
+use crate::glob::GlobSet;
+use crate::matcher::SubstringFinder;
+use crate::searcher::{BinaryMode, MmapChoice, Searcher};
+use crate::types::Types;
+use encoding_rs::Encoding;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// When output is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorMode {
+    #[default]
+    Never,
+    Always,
+    /// Only when standard output is a terminal.
+    Auto,
+}
+
+impl ColorMode {
+    /// Whether output to standard output should be colored.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            ColorMode::Never => false,
+            ColorMode::Always => true,
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+/// How selected lines are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// As grep does, with the prefixes asked for.
+    #[default]
+    Standard,
+    /// `file:line:column:text` for every match, as Vim's `:grep` reads.
+    Vimgrep,
+    /// `file:line:column: text` for every match, as Emacs' compilation mode
+    /// reads.
+    Emacs,
+}
+
+/// Everything that decides what a search looks at, what it selects and how
+/// the results are reported, compiled once up front. It is shared read-only
+/// between threads, so build it once and wrap it in an `Arc`.
+pub struct SearchConfig {
+    matcher: SubstringFinder,
+    searcher: Searcher,
+    invert_match: bool,
+    max_count: Option<u64>,
+    line_number: bool,
+    byte_offset: bool,
+    column: bool,
+    with_filename: bool,
+    no_messages: bool,
+    filter: FileFilter,
+    recursive: bool,
+    files_without_match: bool,
+    count: bool,
+    label: String,
+    search_archives: bool,
+    color: ColorMode,
+    debug: bool,
+    null: bool,
+    null_data: bool,
+    format: OutputFormat,
+}
+
+impl SearchConfig {
+    /// Starts building a configuration searching for the fixed string
+    /// `pattern`.
+    pub fn builder(pattern: &str) -> SearchConfigBuilder {
+        SearchConfigBuilder::new(pattern)
+    }
+
+    pub fn matcher(&self) -> &SubstringFinder {
+        &self.matcher
+    }
+
+    pub fn searcher(&self) -> &Searcher {
+        &self.searcher
+    }
+
+    pub fn invert_match(&self) -> bool {
+        self.invert_match
+    }
+
+    /// The number of selected lines after which to stop, if any.
+    pub fn max_count(&self) -> Option<u64> {
+        self.max_count
+    }
+
+    pub fn line_number(&self) -> bool {
+        self.line_number
+    }
+
+    /// Whether lines are preceded by the offset of their first byte.
+    pub fn byte_offset(&self) -> bool {
+        self.byte_offset
+    }
+
+    /// Whether lines are preceded by the column their first match starts at.
+    pub fn column(&self) -> bool {
+        self.column
+    }
+
+    pub fn with_filename(&self) -> bool {
+        self.with_filename
+    }
+
+    pub fn no_messages(&self) -> bool {
+        self.no_messages
+    }
+
+    /// Decides which files are searched.
+    pub fn filter(&self) -> &FileFilter {
+        &self.filter
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.recursive
+    }
+
+    pub fn files_without_match(&self) -> bool {
+        self.files_without_match
+    }
+
+    pub fn count(&self) -> bool {
+        self.count
+    }
+
+    /// The name standard input is reported under.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn search_archives(&self) -> bool {
+        self.search_archives
+    }
+
+    pub fn color(&self) -> ColorMode {
+        self.color
+    }
+
+    /// Whether to explain on standard error which files are searched or
+    /// skipped, and why.
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    /// Whether file names are followed by a NUL byte.
+    pub fn null(&self) -> bool {
+        self.null
+    }
+
+    /// Whether lines end in a NUL byte instead of a newline.
+    pub fn null_data(&self) -> bool {
+        self.null_data
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+}
+
+/// Collects the options of a `SearchConfig`; see the methods of `Searcher`
+/// for the ones it passes on.
+pub struct SearchConfigBuilder {
+    pattern: String,
+    ignore_case: bool,
+    invert_match: bool,
+    max_count: Option<u64>,
+    line_number: bool,
+    byte_offset: bool,
+    column: bool,
+    with_filename: bool,
+    no_messages: bool,
+    devices: DeviceMode,
+    recursive: bool,
+    files_without_match: bool,
+    count: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    types: Types,
+    select_types: Vec<String>,
+    exclude_types: Vec<String>,
+    max_filesize: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    owner: Option<u32>,
+    group: Option<u32>,
+    binary: BinaryMode,
+    label: String,
+    decompress: bool,
+    search_archives: bool,
+    preprocessor: Option<PathBuf>,
+    preprocessor_glob: Vec<String>,
+    encoding: Option<&'static Encoding>,
+    mmap: MmapChoice,
+    before_context: usize,
+    after_context: usize,
+    color: ColorMode,
+    debug: bool,
+    null: bool,
+    null_data: bool,
+    format: OutputFormat,
+}
+
+impl SearchConfigBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            ignore_case: false,
+            invert_match: false,
+            max_count: None,
+            line_number: false,
+            byte_offset: false,
+            column: false,
+            with_filename: false,
+            no_messages: false,
+            devices: DeviceMode::default(),
+            recursive: false,
+            files_without_match: false,
+            count: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            types: Types::default(),
+            select_types: Vec::new(),
+            exclude_types: Vec::new(),
+            max_filesize: None,
+            modified_after: None,
+            modified_before: None,
+            owner: None,
+            group: None,
+            binary: BinaryMode::default(),
+            label: String::from("(standard input)"),
+            decompress: false,
+            search_archives: false,
+            preprocessor: None,
+            preprocessor_glob: Vec::new(),
+            encoding: None,
+            mmap: MmapChoice::default(),
+            before_context: 0,
+            after_context: 0,
+            color: ColorMode::default(),
+            debug: false,
+            null: false,
+            null_data: false,
+            format: OutputFormat::default(),
+        }
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> Self {
+        self.ignore_case = yes;
+        self
+    }
+
+    pub fn invert_match(mut self, yes: bool) -> Self {
+        self.invert_match = yes;
+        self
+    }
+
+    /// Stop after `count` selected lines; `None` never stops early.
+    pub fn max_count(mut self, count: Option<u64>) -> Self {
+        self.max_count = count;
+        self
+    }
+
+    pub fn line_number(mut self, yes: bool) -> Self {
+        self.line_number = yes;
+        self
+    }
+
+    pub fn byte_offset(mut self, yes: bool) -> Self {
+        self.byte_offset = yes;
+        self
+    }
+
+    pub fn column(mut self, yes: bool) -> Self {
+        self.column = yes;
+        self
+    }
+
+    pub fn with_filename(mut self, yes: bool) -> Self {
+        self.with_filename = yes;
+        self
+    }
+
+    /// Suppress messages about files that cannot be read.
+    pub fn no_messages(mut self, yes: bool) -> Self {
+        self.no_messages = yes;
+        self
+    }
+
+    pub fn devices(mut self, mode: DeviceMode) -> Self {
+        self.devices = mode;
+        self
+    }
+
+    pub fn recursive(mut self, yes: bool) -> Self {
+        self.recursive = yes;
+        self
+    }
+
+    pub fn files_without_match(mut self, yes: bool) -> Self {
+        self.files_without_match = yes;
+        self
+    }
+
+    pub fn count(mut self, yes: bool) -> Self {
+        self.count = yes;
+        self
+    }
+
+    /// Only search files matching one of `globs`.
+    pub fn include<S: AsRef<str>>(mut self, globs: &[S]) -> Self {
+        self.include
+            .extend(globs.iter().map(|g| g.as_ref().to_string()));
+        self
+    }
+
+    /// Skip files matching one of `globs`.
+    pub fn exclude<S: AsRef<str>>(mut self, globs: &[S]) -> Self {
+        self.exclude
+            .extend(globs.iter().map(|g| g.as_ref().to_string()));
+        self
+    }
+
+    /// The file types that `select_types` and `exclude_types` name; the
+    /// built-in ones by default.
+    pub fn types(mut self, types: Types) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Only search files of one of the types `names`.
+    pub fn select_types<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.select_types
+            .extend(names.iter().map(|n| n.as_ref().to_string()));
+        self
+    }
+
+    /// Skip files of the types `names`.
+    pub fn exclude_types<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.exclude_types
+            .extend(names.iter().map(|n| n.as_ref().to_string()));
+        self
+    }
+
+    /// Skip files larger than `size` bytes.
+    pub fn max_filesize(mut self, size: Option<u64>) -> Self {
+        self.max_filesize = size;
+        self
+    }
+
+    /// Skip files last modified before `time`.
+    pub fn modified_after(mut self, time: Option<SystemTime>) -> Self {
+        self.modified_after = time;
+        self
+    }
+
+    /// Skip files last modified after `time`.
+    pub fn modified_before(mut self, time: Option<SystemTime>) -> Self {
+        self.modified_before = time;
+        self
+    }
+
+    /// Skip files not owned by the user `uid`.
+    pub fn owner(mut self, uid: Option<u32>) -> Self {
+        self.owner = uid;
+        self
+    }
+
+    /// Skip files not belonging to the group `gid`.
+    pub fn group(mut self, gid: Option<u32>) -> Self {
+        self.group = gid;
+        self
+    }
+
+    pub fn binary(mut self, mode: BinaryMode) -> Self {
+        self.binary = mode;
+        self
+    }
+
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    pub fn decompress(mut self, yes: bool) -> Self {
+        self.decompress = yes;
+        self
+    }
+
+    /// Search the members of archives instead of the archives themselves.
+    pub fn search_archives(mut self, yes: bool) -> Self {
+        self.search_archives = yes;
+        self
+    }
+
+    pub fn preprocessor(mut self, command: Option<PathBuf>) -> Self {
+        self.preprocessor = command;
+        self
+    }
+
+    /// Only run the preprocessor on files matching one of `globs`.
+    pub fn preprocessor_glob<S: AsRef<str>>(mut self, globs: &[S]) -> Self {
+        self.preprocessor_glob
+            .extend(globs.iter().map(|g| g.as_ref().to_string()));
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn mmap(mut self, choice: MmapChoice) -> Self {
+        self.mmap = choice;
+        self
+    }
+
+    pub fn context(mut self, before: usize, after: usize) -> Self {
+        self.before_context = before;
+        self.after_context = after;
+        self
+    }
+
+    pub fn color(mut self, mode: ColorMode) -> Self {
+        self.color = mode;
+        self
+    }
+
+    pub fn debug(mut self, yes: bool) -> Self {
+        self.debug = yes;
+        self
+    }
+
+    /// Follow file names with a NUL byte rather than ": " or a newline.
+    pub fn null(mut self, yes: bool) -> Self {
+        self.null = yes;
+        self
+    }
+
+    pub fn null_data(mut self, yes: bool) -> Self {
+        self.null_data = yes;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Checks the options and compiles the pattern and globs.
+    pub fn build(self) -> Result<SearchConfig, MygrepError> {
+        if !self.preprocessor_glob.is_empty() && self.preprocessor.is_none() {
+            return Err(MygrepError::Config(String::from(
+                "a preprocessor glob needs a preprocessor",
+            )));
+        }
+        // Offsets count the bytes searched, which --encoding makes UTF-8
+        // rather than the bytes of the file.
+        if self.byte_offset && self.encoding.is_some() {
+            return Err(MygrepError::Config(String::from(
+                "byte offsets cannot be reported for input transcoded by --encoding",
+            )));
+        }
+        if self.max_count == Some(0) {
+            return Err(MygrepError::Config(String::from(
+                "the maximum count must be at least 1",
+            )));
+        }
+        // Input is searched line by line, so no line can hold a newline.
+        if !self.null_data && self.pattern.contains('\n') {
+            return Err(MygrepError::PatternSyntax {
+                pattern: self.pattern,
+                message: String::from("a pattern cannot contain a newline"),
+            });
+        }
+        let globs = |globs: &[String]| {
+            if globs.is_empty() {
+                Ok(None)
+            } else {
+                GlobSet::new(globs).map(Some)
+            }
+        };
+        let file_types = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| self.types.file_type(name))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let filter = FileFilter::new(
+            globs(&self.include)?,
+            globs(&self.exclude)?,
+            self.devices,
+            self.search_archives,
+        )
+        .types(
+            file_types(&self.select_types)?,
+            file_types(&self.exclude_types)?,
+        )
+        .max_filesize(self.max_filesize)
+        .modified(self.modified_after, self.modified_before)
+        .ownership(self.owner, self.group);
+        let searcher = Searcher::new()
+            .invert_match(self.invert_match)
+            .binary(self.binary)
+            .before_context(self.before_context)
+            .after_context(self.after_context)
+            .mmap(self.mmap)
+            .encoding(self.encoding)
+            // Offsets are reported in the bytes of the file, so with them
+            // nothing is transcoded.
+            .ignore_bom(self.byte_offset)
+            .decompress(self.decompress)
+            .null_data(self.null_data)
+            .preprocessor(
+                self.preprocessor,
+                globs(&self.preprocessor_glob)?.map(Arc::new),
+            );
+        Ok(SearchConfig {
+            matcher: SubstringFinder::new(&self.pattern, self.ignore_case),
+            searcher,
+            invert_match: self.invert_match,
+            max_count: self.max_count,
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            column: self.column,
+            with_filename: self.with_filename,
+            no_messages: self.no_messages,
+            filter,
+            recursive: self.recursive,
+            files_without_match: self.files_without_match,
+            count: self.count,
+            label: self.label,
+            search_archives: self.search_archives,
+            color: self.color,
+            debug: self.debug,
+            null: self.null,
+            null_data: self.null_data,
+            format: self.format,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Decision, SkipReason};
+    use crate::matcher::Matcher;
+    use std::path::Path;
+
+    #[test]
+    fn test_build() {
+        let config = SearchConfig::builder("NEEDLE")
+            .ignore_case(true)
+            .include(&["*.rs"])
+            .devices(DeviceMode::Read)
+            .build()
+            .unwrap();
+        assert!(config.matcher().is_match(b"a needle"));
+        let filter = config.filter();
+        assert_eq!(filter.check_member(Path::new("main.rs")), Decision::Search);
+        assert_eq!(
+            filter.check_member(Path::new("main.c")),
+            Decision::Skip(SkipReason::NotIncluded)
+        );
+        assert_eq!(config.label(), "(standard input)");
+    }
+
+    #[test]
+    fn test_build_rejects_bad_options() {
+        let res = SearchConfig::builder("x")
+            .preprocessor_glob(&["*.pdf"])
+            .build();
+        assert!(res.is_err());
+        let res = SearchConfig::builder("x").max_count(Some(0)).build();
+        assert!(res.is_err());
+        let res = SearchConfig::builder("x")
+            .byte_offset(true)
+            .encoding(Some(encoding_rs::UTF_16LE))
+            .build();
+        assert!(matches!(res, Err(MygrepError::Config(_))));
+        let res = SearchConfig::builder("x\ny").build();
+        assert!(matches!(res, Err(MygrepError::PatternSyntax { .. })));
+        let res = SearchConfig::builder("x").exclude(&["*.[z-a]"]).build();
+        assert!(matches!(res, Err(MygrepError::GlobSyntax { .. })));
+        let res = SearchConfig::builder("x")
+            .select_types(&["no-such-type"])
+            .build();
+        assert!(matches!(res, Err(MygrepError::Config(_))));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::inflate::Inflater;
+use crate::process::CommandReader;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Format {
+    /// Recognises a compressed stream by its leading magic bytes.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Format::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Format::Xz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// The external program used to decode formats without a built-in
+    /// decoder.
+    fn program(&self) -> Option<&'static str> {
+        match self {
+            Format::Gzip => None,
+            Format::Bzip2 => Some("bzip2"),
+            Format::Xz => Some("xz"),
+            Format::Zstd => Some("zstd"),
+        }
+    }
+}
+
+/// Opens `path` for searching, transparently decompressing it if it starts
+/// with the magic bytes of a known compression format. gzip is decoded
+/// in-process; bzip2, xz and zstd are piped through their command line
+/// tools.
+pub fn open(file: fs::File, path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(file);
+    let Some(format) = Format::detect(reader.fill_buf()?) else {
+        return Ok(Box::new(reader));
+    };
+    match format.program() {
+        None => Ok(Box::new(BufReader::new(GzDecoder::new(reader)))),
+        Some(program) => {
+            let child = CommandReader::spawn(Command::new(program).arg("-dc").arg(path))?;
+            Ok(Box::new(BufReader::new(child)))
+        }
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn invalid_gzip(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid gzip data: {}", msg),
+    )
+}
+
+/// Decodes gzip data (RFC 1952), including files made of several
+/// concatenated members.
+pub struct GzDecoder<R: Read> {
+    inflater: Inflater<R>,
+    in_member: bool,
+    members: usize,
+    crc: u32,
+    size: u32,
+}
+
+impl<R: Read> GzDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inflater: Inflater::new(inner),
+            in_member: false,
+            members: 0,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        self.inflater
+            .read_byte()?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        let mut value = 0;
+        for shift in [0, 8, 16, 24] {
+            value |= (self.byte()? as u32) << shift;
+        }
+        Ok(value)
+    }
+
+    /// Reads the header of the next member. Returns `false` when the input
+    /// ends instead.
+    fn read_header(&mut self) -> io::Result<bool> {
+        let Some(id1) = self.inflater.read_byte()? else {
+            if self.members == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            return Ok(false);
+        };
+        let id2 = self.byte()?;
+        if (id1, id2) != (0x1f, 0x8b) {
+            if self.members > 0 {
+                // Trailing garbage after a complete member, as gzip itself
+                // tolerates.
+                return Ok(false);
+            }
+            return Err(invalid_gzip("bad magic number"));
+        }
+        if self.byte()? != 8 {
+            return Err(invalid_gzip("unknown compression method"));
+        }
+        let flags = self.byte()?;
+        // Modification time, extra flags and operating system.
+        for _ in 0..6 {
+            self.byte()?;
+        }
+        if flags & 0x04 != 0 {
+            let len = self.byte()? as usize | (self.byte()? as usize) << 8;
+            for _ in 0..len {
+                self.byte()?;
+            }
+        }
+        // File name and comment, both NUL terminated.
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while self.byte()? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.byte()?;
+            self.byte()?;
+        }
+        self.inflater.reset();
+        self.crc = 0;
+        self.size = 0;
+        Ok(true)
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let crc = self.u32_le()?;
+        let size = self.u32_le()?;
+        if crc != self.crc {
+            return Err(invalid_gzip("CRC mismatch"));
+        }
+        if size != self.size {
+            return Err(invalid_gzip("length mismatch"));
+        }
+        self.members += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if !self.in_member {
+                if !self.read_header()? {
+                    return Ok(0);
+                }
+                self.in_member = true;
+            }
+            let n = self.inflater.read(buf)?;
+            if n > 0 {
+                self.crc = crc32_update(self.crc, &buf[..n]);
+                self.size = self.size.wrapping_add(n as u32);
+                return Ok(n);
+            }
+            self.read_trailer()?;
+            self.in_member = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "hello hello hello\n", as written by `gzip -n`.
+    const HELLO_GZ: [u8; 29] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00, 0x3b, 0x7c, 0x8a, 0xdf, 0x12, 0x00, 0x00, 0x00,
+    ];
+
+    fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect(&HELLO_GZ), Some(Format::Gzip));
+        assert_eq!(Format::detect(b"BZh91AY&SY"), Some(Format::Bzip2));
+        assert_eq!(Format::detect(b"\xfd7zXZ\x00\x00"), Some(Format::Xz));
+        assert_eq!(Format::detect(b"\x28\xb5\x2f\xfd\x00"), Some(Format::Zstd));
+        assert_eq!(Format::detect(b"hello"), None);
+        assert_eq!(Format::detect(b"\x1f"), None);
+    }
+
+    #[test]
+    fn test_gunzip() {
+        assert_eq!(gunzip(&HELLO_GZ).unwrap(), b"hello hello hello\n");
+    }
+
+    #[test]
+    fn test_gunzip_multiple_members() {
+        let mut data = HELLO_GZ.to_vec();
+        data.extend(HELLO_GZ);
+        assert_eq!(
+            gunzip(&data).unwrap(),
+            b"hello hello hello\nhello hello hello\n"
+        );
+    }
+
+    #[test]
+    fn test_gunzip_corrupt() {
+        let mut data = HELLO_GZ.to_vec();
+        data[22] ^= 0xff;
+        assert!(gunzip(&data).is_err());
+        assert!(gunzip(&HELLO_GZ[..20]).is_err());
+    }
+}
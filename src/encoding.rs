@@ -0,0 +1,150 @@
+use crate::error::MygrepError;
+use encoding_rs::{CoderResult, Decoder, Encoding};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Looks up an encoding by its WHATWG label, such as `utf-16le`, `latin1`
+/// or `shift_jis`.
+pub fn parse_label(label: &str) -> Result<&'static Encoding, MygrepError> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| MygrepError::Encoding {
+        label: label.to_string(),
+    })
+}
+
+/// Wraps `reader` so it yields UTF-8: decoded from `encoding` if one is
+/// given, or from whatever a leading byte order mark names. Without either,
+/// the bytes are passed through untouched.
+pub fn decode<'a>(
+    mut reader: Box<dyn BufRead + 'a>,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => match Encoding::for_bom(reader.fill_buf()?) {
+            Some((encoding, _)) => encoding,
+            None => return Ok(reader),
+        },
+    };
+    Ok(Box::new(BufReader::new(DecodeReader::new(
+        reader, encoding,
+    ))))
+}
+
+/// Transcodes a byte stream to UTF-8. A byte order mark at the start of the
+/// stream takes precedence over the given encoding and is removed;
+/// malformed input is replaced with U+FFFD.
+pub struct DecodeReader<R: Read> {
+    inner: R,
+    decoder: Decoder,
+    input: Box<[u8]>,
+    in_pos: usize,
+    in_len: usize,
+    eof: bool,
+    output: Box<[u8]>,
+    out_pos: usize,
+    out_len: usize,
+    finished: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder(),
+            input: vec![0; 8 * 1024].into_boxed_slice(),
+            in_pos: 0,
+            in_len: 0,
+            eof: false,
+            output: vec![0; 32 * 1024].into_boxed_slice(),
+            out_pos: 0,
+            out_len: 0,
+            finished: false,
+        }
+    }
+
+    /// Decodes the next batch of output, leaving it empty only at the end.
+    fn fill(&mut self) -> io::Result<()> {
+        while !self.finished {
+            if self.in_pos == self.in_len && !self.eof {
+                self.in_len = loop {
+                    match self.inner.read(&mut self.input) {
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        res => break res?,
+                    }
+                };
+                self.in_pos = 0;
+                self.eof = self.in_len == 0;
+            }
+            let (result, read, written, _) = self.decoder.decode_to_utf8(
+                &self.input[self.in_pos..self.in_len],
+                &mut self.output,
+                self.eof,
+            );
+            self.in_pos += read;
+            self.out_pos = 0;
+            self.out_len = written;
+            if self.eof && result == CoderResult::InputEmpty {
+                self.finished = true;
+            }
+            if written > 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.out_len {
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out_len - self.out_pos);
+        buf[..n].copy_from_slice(&self.output[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(data: &[u8], encoding: Option<&'static Encoding>) -> Vec<u8> {
+        let mut out = Vec::new();
+        decode(Box::new(data), encoding)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_utf16_bom() {
+        let data = b"\xff\xfeE\0R\0R\0\n\0o\0k\0\n\0";
+        assert_eq!(decode_all(data, None), b"ERR\nok\n");
+        let data = b"\xfe\xff\0E\0R\0R\0\n";
+        assert_eq!(decode_all(data, None), b"ERR\n");
+    }
+
+    #[test]
+    fn test_utf8_bom_is_removed() {
+        assert_eq!(decode_all(b"\xef\xbb\xbfabc\n", None), b"abc\n");
+    }
+
+    #[test]
+    fn test_no_bom_passes_bytes_through() {
+        assert_eq!(decode_all(b"caf\xe9\n", None), b"caf\xe9\n");
+    }
+
+    #[test]
+    fn test_explicit_encoding() {
+        let latin1 = parse_label("latin1").unwrap();
+        assert_eq!(decode_all(b"caf\xe9\n", Some(latin1)), "café\n".as_bytes());
+        let sjis = parse_label("shift_jis").unwrap();
+        assert_eq!(
+            decode_all(b"\x83e\x83X\x83g\n", Some(sjis)),
+            "テスト\n".as_bytes()
+        );
+        assert!(parse_label("no-such-encoding").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that can go wrong in a search. Displayed as `path: message`
+/// for errors about a file, matching how grep reports them.
+#[derive(Debug)]
+pub enum MygrepError {
+    /// Reading or opening `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// `path` could not be opened or read for lack of permission.
+    Permission { path: PathBuf },
+    /// A glob that is not valid.
+    GlobSyntax { glob: String, message: String },
+    /// A search pattern that is not valid.
+    PatternSyntax { pattern: String, message: String },
+    /// An encoding label that names no known encoding.
+    Encoding { label: String },
+    /// A combination of options that cannot be used together.
+    Config(String),
+}
+
+impl MygrepError {
+    /// Attributes `error` to `path`.
+    pub fn io<P: Into<PathBuf>>(path: P, error: io::Error) -> Self {
+        let path = path.into();
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            MygrepError::Permission { path }
+        } else {
+            MygrepError::Io {
+                path,
+                source: error,
+            }
+        }
+    }
+
+    /// The file the error is about, if it is about one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            MygrepError::Io { path, .. } | MygrepError::Permission { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// The message of `error`, without the "(os error N)" Rust appends to errors
+/// coming from the operating system.
+pub(crate) fn describe(error: &io::Error) -> String {
+    let message = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => message
+            .strip_suffix(&format!(" (os error {})", code))
+            .map_or(message.clone(), str::to_string),
+        None => message,
+    }
+}
+
+impl fmt::Display for MygrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MygrepError::Io { path, source } => {
+                write!(f, "{}: {}", path.display(), describe(source))
+            }
+            MygrepError::Permission { path } => write!(f, "{}: Permission denied", path.display()),
+            MygrepError::GlobSyntax { glob, message } => {
+                write!(f, "invalid glob '{}': {}", glob, message)
+            }
+            MygrepError::PatternSyntax { pattern, message } => {
+                write!(f, "invalid pattern '{}': {}", pattern, message)
+            }
+            MygrepError::Encoding { label } => write!(f, "unknown encoding '{}'", label),
+            MygrepError::Config(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for MygrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MygrepError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = MygrepError::io("a.txt", io::Error::from_raw_os_error(2));
+        assert_eq!(error.to_string(), "a.txt: No such file or directory");
+        assert_eq!(error.path(), Some(Path::new("a.txt")));
+        let error = MygrepError::io("b", io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(matches!(error, MygrepError::Permission { .. }));
+        assert_eq!(error.to_string(), "b: Permission denied");
+        let error = MygrepError::io("c.gz", io::Error::other("invalid gzip data"));
+        assert_eq!(error.to_string(), "c.gz: invalid gzip data");
+    }
+}
@@ -0,0 +1,451 @@
+use crate::archive;
+use crate::error::MygrepError;
+use crate::glob::GlobSet;
+use crate::types::FileType;
+use std::fmt;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Parses a file size such as `4096`, `512K`, `10M` or `1G`. The suffixes
+/// are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64, MygrepError> {
+    let (digits, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let shift = match unit {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        _ => 64,
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .filter(|_| shift < 64)
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| {
+            MygrepError::Config(format!(
+                "invalid size '{}': expected a number of bytes, optionally followed by K, M or G",
+                size
+            ))
+        })
+}
+
+/// Parses an age such as `90s`, `30m`, `12h`, `2d` or `1w`. A number
+/// without a unit is in seconds.
+pub fn parse_age(age: &str) -> Result<Duration, MygrepError> {
+    let (digits, unit) = match age.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => age.split_at(i),
+        None => (age, ""),
+    };
+    let seconds = match unit {
+        "" | "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        "w" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+    seconds
+        .zip(digits.parse::<u64>().ok())
+        .and_then(|(seconds, n)| n.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(|| {
+            MygrepError::Config(format!(
+                "invalid age '{}': expected a number followed by s, m, h, d or w",
+                age
+            ))
+        })
+}
+
+/// How devices, FIFOs and sockets are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DeviceMode {
+    /// Read them like regular files.
+    Read,
+    /// Skip them.
+    #[default]
+    Skip,
+}
+
+/// Whether a file is searched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Search,
+    Skip(SkipReason),
+}
+
+/// Why a file is not searched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// --include was given and no glob of it matches.
+    NotIncluded,
+    /// This glob of --exclude matches.
+    Excluded(String),
+    /// -t was given and none of the types matches.
+    NotType,
+    /// The glob `glob` of the type `name`, excluded by -T, matches.
+    TypeExcluded { name: String, glob: String },
+    /// It is a directory reached through a symbolic link while walking,
+    /// which is not followed.
+    Directory,
+    /// It is a device, FIFO or socket and those are skipped.
+    Device,
+    /// It is larger than --max-filesize.
+    TooLarge { size: u64, max: u64 },
+    /// It was last modified before --newer-than or --newer asks for.
+    TooOld,
+    /// It was last modified after --older-than asks for.
+    TooNew,
+    /// It is owned by the user `uid`, not the one --owner asks for.
+    OtherOwner(u32),
+    /// It belongs to the group `gid`, not the one --group asks for.
+    OtherGroup(u32),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotIncluded => f.write_str("no --include glob matches"),
+            SkipReason::Excluded(glob) => write!(f, "--exclude glob '{}' matches", glob),
+            SkipReason::NotType => f.write_str("not of a type selected by -t"),
+            SkipReason::TypeExcluded { name, glob } => {
+                write!(
+                    f,
+                    "glob '{}' of type '{}' excluded by -T matches",
+                    glob, name
+                )
+            }
+            SkipReason::Directory => f.write_str("a symbolic link to a directory"),
+            SkipReason::Device => f.write_str("not a regular file, and --devices=skip"),
+            SkipReason::TooLarge { size, max } => {
+                write!(f, "{} bytes, over --max-filesize of {} bytes", size, max)
+            }
+            SkipReason::TooOld => f.write_str("modified before --newer-than or --newer"),
+            SkipReason::TooNew => f.write_str("modified after --older-than"),
+            SkipReason::OtherOwner(uid) => write!(f, "owned by user {}, not --owner", uid),
+            SkipReason::OtherGroup(gid) => write!(f, "of group {}, not --group", gid),
+        }
+    }
+}
+
+/// Decides which files a search looks at. Files named on the command line
+/// and files found walking a directory go through the same checks.
+#[derive(Default)]
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    types: Vec<FileType>,
+    types_not: Vec<FileType>,
+    devices: DeviceMode,
+    search_archives: bool,
+    max_filesize: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    owner: Option<u32>,
+    group: Option<u32>,
+}
+
+impl FileFilter {
+    /// A filter with the given --include and --exclude globs, if any.
+    /// Archives are searched member by member when `search_archives` is
+    /// set, so --include is then matched against their members instead.
+    pub fn new(
+        include: Option<GlobSet>,
+        exclude: Option<GlobSet>,
+        devices: DeviceMode,
+        search_archives: bool,
+    ) -> Self {
+        FileFilter {
+            include,
+            exclude,
+            devices,
+            search_archives,
+            ..Default::default()
+        }
+    }
+
+    /// Only search files of one of the types `select`, if any are given,
+    /// and skip files of the types `exclude`. Like --include, the selected
+    /// types are matched against the members of archives that are searched.
+    pub fn types(mut self, select: Vec<FileType>, exclude: Vec<FileType>) -> Self {
+        self.types = select;
+        self.types_not = exclude;
+        self
+    }
+
+    /// Skip regular files larger than `size` bytes.
+    pub fn max_filesize(mut self, size: Option<u64>) -> Self {
+        self.max_filesize = size;
+        self
+    }
+
+    /// Only search files last modified after `after` and before `before`.
+    /// Files whose modification time is unknown pass.
+    pub fn modified(mut self, after: Option<SystemTime>, before: Option<SystemTime>) -> Self {
+        self.modified_after = after;
+        self.modified_before = before;
+        self
+    }
+
+    /// Only search files owned by the user `uid` and the group `gid`.
+    pub fn ownership(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.owner = uid;
+        self.group = gid;
+        self
+    }
+
+    /// Decides whether to search the file `path`, which is `relative` to
+    /// the directory it was found under (or `path` itself when it was named
+    /// directly), and whose metadata is `metadata`.
+    pub fn check(&self, path: &Path, relative: &Path, metadata: &Metadata) -> Decision {
+        let is_archive = self.search_archives && archive::Kind::detect(path).is_some();
+        let decision = self.check_name(path, relative, is_archive);
+        if decision != Decision::Search {
+            return decision;
+        }
+        if metadata.is_dir() {
+            return Decision::Skip(SkipReason::Directory);
+        }
+        if self.devices == DeviceMode::Skip && !metadata.is_file() {
+            return Decision::Skip(SkipReason::Device);
+        }
+        if let Some(max) = self.max_filesize
+            && metadata.is_file()
+            && metadata.len() > max
+        {
+            let size = metadata.len();
+            return Decision::Skip(SkipReason::TooLarge { size, max });
+        }
+        if let Ok(modified) = metadata.modified() {
+            if self.modified_after.is_some_and(|after| modified <= after) {
+                return Decision::Skip(SkipReason::TooOld);
+            }
+            if self
+                .modified_before
+                .is_some_and(|before| modified >= before)
+            {
+                return Decision::Skip(SkipReason::TooNew);
+            }
+        }
+        if self.owner.is_some_and(|uid| metadata.uid() != uid) {
+            return Decision::Skip(SkipReason::OtherOwner(metadata.uid()));
+        }
+        if self.group.is_some_and(|gid| metadata.gid() != gid) {
+            return Decision::Skip(SkipReason::OtherGroup(metadata.gid()));
+        }
+        Decision::Search
+    }
+
+    /// Decides whether to search the archive member `member`.
+    pub fn check_member(&self, member: &Path) -> Decision {
+        self.check_name(member, member, false)
+    }
+
+    /// The checks of `check` that only look at the name of the file. Those
+    /// selecting files are passed over for archives, which are selected by
+    /// their members instead.
+    fn check_name(&self, path: &Path, relative: &Path, is_archive: bool) -> Decision {
+        if let Some(include) = &self.include
+            && !is_archive
+            && !include.is_file_match(path, relative)
+        {
+            return Decision::Skip(SkipReason::NotIncluded);
+        }
+        if let Some(glob) = self
+            .exclude
+            .as_ref()
+            .and_then(|exclude| exclude.file_match(path, relative))
+        {
+            return Decision::Skip(SkipReason::Excluded(glob.to_string()));
+        }
+        if !self.types.is_empty()
+            && !is_archive
+            && !self
+                .types
+                .iter()
+                .any(|t| t.globs().is_file_match(path, relative))
+        {
+            return Decision::Skip(SkipReason::NotType);
+        }
+        for file_type in &self.types_not {
+            if let Some(glob) = file_type.globs().file_match(path, relative) {
+                return Decision::Skip(SkipReason::TypeExcluded {
+                    name: file_type.name().to_string(),
+                    glob: glob.to_string(),
+                });
+            }
+        }
+        Decision::Search
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Types;
+    use std::fs;
+
+    fn globs(globs: &[&str]) -> Option<GlobSet> {
+        Some(GlobSet::new(globs).unwrap())
+    }
+
+    #[test]
+    fn test_check() {
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let metadata = fs::metadata(&manifest).unwrap();
+        let relative = Path::new("Cargo.toml");
+        let filter = FileFilter::default();
+        assert_eq!(
+            filter.check(&manifest, relative, &metadata),
+            Decision::Search
+        );
+        let filter = FileFilter::new(globs(&["*.rs"]), None, DeviceMode::Skip, false);
+        assert_eq!(
+            filter.check(&manifest, relative, &metadata),
+            Decision::Skip(SkipReason::NotIncluded)
+        );
+        let filter = FileFilter::new(
+            globs(&["*.toml"]),
+            globs(&["Cargo.*"]),
+            DeviceMode::Skip,
+            false,
+        );
+        assert_eq!(
+            filter.check(&manifest, relative, &metadata),
+            Decision::Skip(SkipReason::Excluded(String::from("Cargo.*")))
+        );
+    }
+
+    #[test]
+    fn test_check_device() {
+        let null = Path::new("/dev/null");
+        let metadata = fs::metadata(null).unwrap();
+        let filter = FileFilter::new(None, None, DeviceMode::Skip, false);
+        assert_eq!(
+            filter.check(null, null, &metadata),
+            Decision::Skip(SkipReason::Device)
+        );
+        let filter = FileFilter::new(None, None, DeviceMode::Read, false);
+        assert_eq!(filter.check(null, null, &metadata), Decision::Search);
+        let tmp = std::env::temp_dir();
+        let metadata = fs::metadata(&tmp).unwrap();
+        assert_eq!(
+            filter.check(&tmp, &tmp, &metadata),
+            Decision::Skip(SkipReason::Directory)
+        );
+    }
+
+    #[test]
+    fn test_check_types() {
+        let types = Types::new();
+        let filter = FileFilter::default().types(
+            vec![
+                types.file_type("rust").unwrap(),
+                types.file_type("toml").unwrap(),
+            ],
+            vec![types.file_type("js").unwrap()],
+        );
+        assert_eq!(
+            filter.check_member(Path::new("src/lib.rs")),
+            Decision::Search
+        );
+        assert_eq!(
+            filter.check_member(Path::new("Cargo.toml")),
+            Decision::Search
+        );
+        assert_eq!(
+            filter.check_member(Path::new("README.md")),
+            Decision::Skip(SkipReason::NotType)
+        );
+        let filter = FileFilter::default().types(Vec::new(), vec![types.file_type("js").unwrap()]);
+        assert_eq!(
+            filter.check_member(Path::new("app.mjs")),
+            Decision::Skip(SkipReason::TypeExcluded {
+                name: String::from("js"),
+                glob: String::from("*.mjs"),
+            })
+        );
+        assert_eq!(filter.check_member(Path::new("app.ts")), Decision::Search);
+    }
+
+    #[test]
+    fn test_check_metadata() {
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let metadata = fs::metadata(&manifest).unwrap();
+        let size = metadata.len();
+        let modified = metadata.modified().unwrap();
+        let check = |filter: FileFilter| filter.check(&manifest, &manifest, &metadata);
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            check(FileFilter::default().max_filesize(Some(size))),
+            Decision::Search
+        );
+        assert_eq!(
+            check(FileFilter::default().max_filesize(Some(size - 1))),
+            Decision::Skip(SkipReason::TooLarge {
+                size,
+                max: size - 1
+            })
+        );
+        assert_eq!(
+            check(FileFilter::default().modified(Some(modified - second), Some(modified + second))),
+            Decision::Search
+        );
+        assert_eq!(
+            check(FileFilter::default().modified(Some(modified + second), None)),
+            Decision::Skip(SkipReason::TooOld)
+        );
+        assert_eq!(
+            check(FileFilter::default().modified(None, Some(modified - second))),
+            Decision::Skip(SkipReason::TooNew)
+        );
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+        assert_eq!(
+            check(FileFilter::default().ownership(Some(uid), Some(gid))),
+            Decision::Search
+        );
+        assert_eq!(
+            check(FileFilter::default().ownership(Some(uid.wrapping_add(1)), None)),
+            Decision::Skip(SkipReason::OtherOwner(uid))
+        );
+        assert_eq!(
+            check(FileFilter::default().ownership(None, Some(gid.wrapping_add(1)))),
+            Decision::Skip(SkipReason::OtherGroup(gid))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("10M").unwrap(), 10 << 20);
+        assert_eq!(parse_size("1g").unwrap(), 1 << 30);
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+        assert_eq!(parse_age("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_age("2d").unwrap(), Duration::from_secs(2 * 86400));
+        assert_eq!(parse_age("1w").unwrap(), Duration::from_secs(7 * 86400));
+        assert!(parse_age("2 days").is_err());
+        assert!(parse_age("-1d").is_err());
+    }
+
+    #[test]
+    fn test_check_archive() {
+        let filter = FileFilter::new(globs(&["*.rs"]), None, DeviceMode::Skip, true);
+        assert_eq!(
+            filter.check_member(Path::new("src/main.rs")),
+            Decision::Search
+        );
+        assert_eq!(
+            filter.check_member(Path::new("README.md")),
+            Decision::Skip(SkipReason::NotIncluded)
+        );
+    }
+}
@@ -1,108 +1,402 @@
-use std::ffi::OsStr;
+use crate::error::MygrepError;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
+/// A single matching unit inside one path segment of a glob.
+#[derive(Debug, Clone)]
+enum Token {
+    /// A character that must appear verbatim (possibly escaped with `\`).
+    Literal(char),
+    /// `?`: exactly one character.
+    AnyChar,
+    /// `*`: any run of characters, including none.
+    AnyString,
+    /// `[...]` or `[!...]`: one character from (or not from) a set of ranges.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// One unit of a path being matched: a decoded character, or a byte that is
+/// not part of valid UTF-8.
+type Unit = Result<char, u8>;
+
+impl Token {
+    fn matches(&self, unit: Unit) -> bool {
+        match (self, unit) {
+            (Token::Literal(l), Ok(c)) => *l == c,
+            (Token::Literal(_), Err(_)) => false,
+            (Token::AnyChar, _) => true,
+            (Token::AnyString, _) => true,
+            (Token::Class { negated, ranges }, Ok(c)) => {
+                let found = ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi);
+                found != *negated
+            }
+            (Token::Class { negated, .. }, Err(_)) => *negated,
+        }
+    }
+}
+
+/// Splits `bytes` into units, keeping bytes that are not valid UTF-8 as they
+/// are instead of replacing them.
+fn decode_units(bytes: &[u8]) -> Vec<Unit> {
+    let mut units = Vec::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        units.extend(chunk.valid().chars().map(Ok));
+        units.extend(chunk.invalid().iter().map(|b| Err(*b)));
+    }
+    units
+}
+
+/// One `/`-separated component of a glob.
+#[derive(Debug, Clone)]
+enum Segment {
+    /// `**` on its own: any number of directories, including none.
+    AnyDirs,
+    Pattern(Vec<Token>),
+}
+
+/// A compiled glob pattern.
+///
+/// Supported syntax:
+/// * `*` matches any run of characters within a path segment
+/// * `?` matches exactly one character
+/// * `[abc]`, `[a-z]` match one character from the set; `[!a-z]` or
+///   `[^a-z]` match one character outside it
+/// * `{foo,bar}` matches either alternative (alternatives may nest)
+/// * `**` as a whole segment matches any number of directories, including
+///   none, as in gitignore
+/// * `\` escapes the following character
+///
+/// A `{` or `[` that is never closed is matched literally. A class range
+/// whose ends are out of order, such as `[z-a]`, is a syntax error.
+///
+/// Paths are matched on their raw bytes, so names that are not valid UTF-8
+/// can still be matched; such bytes only ever match `*`, `?` or a negated
+/// class.
 pub struct Glob {
-    glob_pattern: String,
+    alternatives: Vec<Vec<Segment>>,
 }
 
 impl Glob {
-    pub fn new(pattern: &String) -> Self {
-        Self {
-            glob_pattern: pattern.clone(),
+    pub fn new(pattern: &str) -> Result<Self, MygrepError> {
+        let syntax_error = |message: &str| MygrepError::GlobSyntax {
+            glob: pattern.to_string(),
+            message: message.to_string(),
+        };
+        let alternatives = Self::expand_braces(pattern)
+            .iter()
+            .map(|alt| {
+                Path::new(alt)
+                    .iter()
+                    .map(|seg| {
+                        let seg = seg.to_string_lossy();
+                        if seg == "**" {
+                            Ok(Segment::AnyDirs)
+                        } else {
+                            Self::tokenize(&seg).map(Segment::Pattern)
+                        }
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()
+            .map_err(syntax_error)?;
+        Ok(Self { alternatives })
+    }
+
+    /// Expands every `{a,b,...}` group into separate patterns. Braces without
+    /// a top-level comma, or without a closing brace, are kept literally.
+    fn expand_braces(pattern: &str) -> Vec<String> {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => i += 2,
+                '{' => {
+                    if let Some((end, commas)) = Self::find_brace_end(&chars, i)
+                        && !commas.is_empty()
+                    {
+                        let prefix = chars[..i].iter().collect::<String>();
+                        let suffix = chars[end + 1..].iter().collect::<String>();
+                        let mut bounds = vec![i];
+                        bounds.extend(commas);
+                        bounds.push(end);
+                        return bounds
+                            .windows(2)
+                            .flat_map(|w| {
+                                let alt = chars[w[0] + 1..w[1]].iter().collect::<String>();
+                                Self::expand_braces(&format!("{}{}{}", prefix, alt, suffix))
+                            })
+                            .collect();
+                    }
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+        vec![pattern.to_string()]
+    }
+
+    /// Finds the `}` closing the brace at `open`, along with the positions of
+    /// the commas directly inside it.
+    fn find_brace_end(chars: &[char], open: usize) -> Option<(usize, Vec<usize>)> {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut j = open;
+        while j < chars.len() {
+            match chars[j] {
+                '\\' => {
+                    j += 2;
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((j, commas));
+                    }
+                }
+                ',' if depth == 1 => commas.push(j),
+                _ => {}
+            }
+            j += 1;
+        }
+        None
+    }
+
+    fn tokenize(segment: &str) -> Result<Vec<Token>, &'static str> {
+        let chars = segment.chars().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Literal(chars[i + 1]));
+                    i += 2;
+                    continue;
+                }
+                '?' => tokens.push(Token::AnyChar),
+                '*' => {
+                    if !matches!(tokens.last(), Some(Token::AnyString)) {
+                        tokens.push(Token::AnyString);
+                    }
+                }
+                '[' => {
+                    if let Some((class, next)) = Self::parse_class(&chars, i) {
+                        if let Token::Class { ranges, .. } = &class
+                            && ranges.iter().any(|(lo, hi)| lo > hi)
+                        {
+                            return Err("range out of order in '[...]'");
+                        }
+                        tokens.push(class);
+                        i = next;
+                        continue;
+                    }
+                    tokens.push(Token::Literal('['));
+                }
+                c => tokens.push(Token::Literal(c)),
+            }
+            i += 1;
         }
+        Ok(tokens)
     }
 
-    fn is_file_pattern_match(pattern: &OsStr, leafname: &OsStr) -> bool {
-        // TODO: do something other than unwrap
-        let pat_leaf = pattern.to_str().unwrap().chars().collect::<Vec<_>>();
-        let pth_leaf = leafname.to_str().unwrap().chars().collect::<Vec<_>>();
-        let (mut i, mut j) = (0, 0);
-        let mut star_pos = pat_leaf.len();
-        while i < pat_leaf.len() && j < pth_leaf.len() {
-            if pat_leaf[i] == '*' {
-                star_pos = i;
-                break;
-            } else if pat_leaf[i] != pth_leaf[j] {
-                return false;
+    /// Parses the class starting at `chars[start] == '['`, returning it and
+    /// the index just past its closing `]`.
+    fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+        let mut i = start + 1;
+        let negated = i < chars.len() && (chars[i] == '!' || chars[i] == '^');
+        if negated {
+            i += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let (lo, next) = match chars.get(i)? {
+                ']' if !first => return Some((Token::Class { negated, ranges }, i + 1)),
+                '\\' => (*chars.get(i + 1)?, i + 2),
+                c => (*c, i + 1),
+            };
+            i = next;
+            first = false;
+            if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|c| *c != ']') {
+                let (hi, next) = match chars[i + 1] {
+                    '\\' => (*chars.get(i + 2)?, i + 3),
+                    c => (c, i + 2),
+                };
+                ranges.push((lo, hi));
+                i = next;
             } else {
-                i += 1;
-                j += 1;
+                ranges.push((lo, lo));
             }
         }
-        if i == pat_leaf.len() && j == pth_leaf.len() {
-            return true;
+    }
+
+    fn is_tokens_match(tokens: &[Token], text: &[Unit]) -> bool {
+        let (mut t, mut s) = (0, 0);
+        // Position of the last `*` seen and the text index it is resumed from
+        // when a later token fails to match.
+        let mut star: Option<(usize, usize)> = None;
+        while s < text.len() {
+            if t < tokens.len() {
+                if let Token::AnyString = tokens[t] {
+                    star = Some((t, s));
+                    t += 1;
+                    continue;
+                }
+                if tokens[t].matches(text[s]) {
+                    t += 1;
+                    s += 1;
+                    continue;
+                }
+            }
+            match star {
+                Some((star_t, star_s)) => {
+                    t = star_t + 1;
+                    s = star_s + 1;
+                    star = Some((star_t, star_s + 1));
+                }
+                None => return false,
+            }
         }
-        i = pat_leaf.len() - 1;
-        j = pth_leaf.len() - 1;
-        while i > star_pos && j > 0 {
-            if pat_leaf[i] != pth_leaf[j] {
-                return false;
+        tokens[t..].iter().all(|t| matches!(t, Token::AnyString))
+    }
+
+    fn is_segments_match(segments: &[Segment], path: &[&OsStr]) -> bool {
+        match segments.split_first() {
+            None => path.is_empty(),
+            Some((Segment::AnyDirs, [])) => true,
+            Some((Segment::AnyDirs, rest)) => {
+                (0..=path.len()).any(|i| Self::is_segments_match(rest, &path[i..]))
             }
-            i -= 1;
-            j -= 1;
+            Some((Segment::Pattern(tokens), rest)) => match path.split_first() {
+                Some((name, tail)) => {
+                    Self::is_tokens_match(tokens, &decode_units(name.as_bytes()))
+                        && Self::is_segments_match(rest, tail)
+                }
+                None => false,
+            },
         }
-        if i > star_pos && j == 0 && pat_leaf[i] != pth_leaf[j] {
-            return false;
+    }
+
+    pub fn is_match<P: AsRef<Path> + ?Sized>(&self, path: &P) -> bool {
+        let path = path.as_ref().iter().collect::<Vec<_>>();
+        self.alternatives
+            .iter()
+            .any(|segments| Self::is_segments_match(segments, &path))
+    }
+}
+
+/// Several globs compiled once and matched against a path together.
+///
+/// Following gitignore conventions, a glob without a `/` (other than a
+/// trailing one) is matched against the basename of the path, while a glob
+/// containing a `/` is matched against the whole path relative to the search
+/// root, a leading `/` anchoring it to that root.
+///
+/// Basename globs that are plain names or of the form `*.ext` are looked up
+/// by hash instead of being run through the matcher one by one.
+pub struct GlobSet {
+    patterns: Vec<String>,
+    globs: Vec<(usize, Glob)>,
+    path_globs: Vec<(usize, Glob)>,
+    literals: HashMap<OsString, Vec<usize>>,
+    extensions: HashMap<OsString, Vec<usize>>,
+}
+
+impl GlobSet {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, MygrepError> {
+        let mut set = Self {
+            patterns: Vec::new(),
+            globs: Vec::new(),
+            path_globs: Vec::new(),
+            literals: HashMap::new(),
+            extensions: HashMap::new(),
+        };
+        for (i, pattern) in patterns.iter().enumerate() {
+            let pattern = pattern.as_ref();
+            set.patterns.push(pattern.to_string());
+            if pattern.trim_end_matches('/').contains('/') {
+                let anchored = pattern.strip_prefix('/').unwrap_or(pattern);
+                let anchored = anchored.trim_start_matches("./");
+                set.path_globs.push((i, Glob::new(anchored)?));
+            } else if !pattern.contains(Self::is_meta) {
+                set.literals.entry(pattern.into()).or_default().push(i);
+            } else if let Some(ext) = pattern.strip_prefix('*')
+                && ext.starts_with('.')
+                && !ext.contains(Self::is_meta)
+            {
+                set.extensions.entry(ext.into()).or_default().push(i);
+            } else {
+                set.globs.push((i, Glob::new(pattern)?));
+            }
         }
-        return true;
+        Ok(set)
     }
 
-    pub fn is_match<S: AsRef<str>>(&self, filename: &S) -> bool {
-        let path_pattern = Path::new(&self.glob_pattern);
-        let path = Path::new(filename.as_ref());
-        let mut pat_iter = path_pattern.iter().peekable();
-        let mut pth_iter = path.iter().peekable();
-        loop {
-            let next_pat = pat_iter.next();
-            let next_pth = pth_iter.next();
-            // pat = ./foo/bar.py, pth = ./foo/bar.py
-            // pat = ./**/*.py, pth = ./foo/bar.py
-            match (next_pat, next_pth) {
-                (Some(p), Some(q)) => {
-                    if pth_iter.peek().is_some() {
-                        if pat_iter.peek().is_some() {
-                            if p != "**" && p != q {
-                                return false;
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            if p == "**" {
-                                return true;
-                            } else {
-                                return false;
-                            }
-                        }
-                    } else {
-                        let pattern = p.to_str().unwrap();
-                        if pat_iter.peek().is_some() {
-                            if pattern == "**" {
-                                let next = pat_iter.next().unwrap();
-                                let next_str = next.to_str().unwrap();
-                                if next_str.contains('*') {
-                                    return Self::is_file_pattern_match(next, q);
-                                } else if next != q {
-                                    return false;
-                                }
-                                return true;
-                            } else {
-                                return false;
-                            }
-                        } else if p == "**" {
-                            return true;
-                        } else if pattern.contains('*') {
-                            return Self::is_file_pattern_match(p, q);
-                        } else if p != q {
-                            return false;
-                        }
-                        return true;
-                    }
+    fn is_meta(c: char) -> bool {
+        matches!(c, '*' | '?' | '[' | '{' | '\\')
+    }
+
+    /// The index of the first of the globs that is `pattern` verbatim.
+    pub fn position<S: AsRef<OsStr> + ?Sized>(&self, pattern: &S) -> Option<usize> {
+        let pattern = pattern.as_ref();
+        self.patterns.iter().position(|p| OsStr::new(p) == pattern)
+    }
+
+    pub fn is_match<P: AsRef<Path> + ?Sized>(&self, path: &P) -> bool {
+        !self.matches(path).is_empty()
+    }
+
+    /// Whether `filename` is named by one of the globs, either verbatim or
+    /// by matching `relative`, its path relative to the search root.
+    pub fn is_file_match(&self, filename: &Path, relative: &Path) -> bool {
+        self.file_match(filename, relative).is_some()
+    }
+
+    /// The glob naming `filename`, as for `is_file_match`. When several do,
+    /// the one given first.
+    pub fn file_match(&self, filename: &Path, relative: &Path) -> Option<&str> {
+        self.position(filename)
+            .or_else(|| self.matches(relative).first().copied())
+            .map(|i| self.patterns[i].as_str())
+    }
+
+    /// Indices, into the patterns the set was built from, of every glob
+    /// matching `path`, in ascending order. `path` is relative to the search
+    /// root.
+    pub fn matches<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Vec<usize> {
+        let mut relative = path.as_ref();
+        while let Ok(rest) = relative.strip_prefix(".") {
+            relative = rest;
+        }
+        let mut result = Vec::new();
+        if let Some(basename) = relative.file_name() {
+            if let Some(indices) = self.literals.get(basename) {
+                result.extend(indices);
+            }
+            let bytes = basename.as_bytes();
+            for (pos, _) in bytes.iter().enumerate().filter(|(_, b)| **b == b'.') {
+                if let Some(indices) = self.extensions.get(OsStr::from_bytes(&bytes[pos..])) {
+                    result.extend(indices);
+                }
+            }
+            for (i, glob) in self.globs.iter() {
+                if glob.is_match(&basename) {
+                    result.push(*i);
                 }
-                (None, None) => return true,
-                (Some(p), None) if p == "**" => return true,
-                (_, _) => return false,
             }
         }
+        for (i, glob) in self.path_globs.iter() {
+            if glob.is_match(&relative) {
+                result.push(*i);
+            }
+        }
+        result.sort_unstable();
+        result
     }
 }
 
@@ -114,21 +408,21 @@ mod tests {
     fn test_glob_1() {
         let path = String::from("./foo/bar.py");
         let pattern = String::from("./foo/bar.py");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_2() {
         let pattern = String::from("./**/bar.py");
         let path = String::from("./foo/bar.py");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_3() {
         let pattern = String::from("./**/*.py");
         let path = String::from("./foo/bar.py");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
@@ -136,8 +430,8 @@ mod tests {
         let pattern = String::from("./**/*");
         let path_1 = String::from("./foo/bar.py");
         let path_2 = String::from("./foo/baz.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path_1), true);
-        assert_eq!(Glob::new(&pattern).is_match(&path_2), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_1));
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_2));
     }
 
     #[test]
@@ -147,65 +441,225 @@ mod tests {
         let path_2 = String::from("./foo/baz.txt");
         let path_3 = String::from("./foo/bar/baz.txt");
         let path_4 = String::from("./foo/");
-        assert_eq!(Glob::new(&pattern).is_match(&path_1), true);
-        assert_eq!(Glob::new(&pattern).is_match(&path_2), true);
-        assert_eq!(Glob::new(&pattern).is_match(&path_3), true);
-        assert_eq!(Glob::new(&pattern).is_match(&path_4), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_1));
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_2));
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_3));
+        assert!(Glob::new(&pattern).unwrap().is_match(&path_4));
     }
 
     #[test]
     fn test_glob_6() {
         let pattern = String::from("foo/bar/baz/**/a.txt");
         let path = String::from("foo/bar/baz/a.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
         let pattern = String::from("foo/bar/baz/**/*.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_7() {
         let pattern = String::from("foo/**/bar/baz/a.txt");
         let path = String::from("foo/bar/bar/baz/a.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), true);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_8() {
         let pattern = String::from("foo/**/bar.txt");
         let path = String::from("foo/baz.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
         let path = String::from("bar/bar.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
         let path = String::from("foo/bar");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_9() {
         let pattern = String::from("foo/**/*.txt");
         let path = String::from("foo/bar/baz.py");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_10() {
         let pattern = String::from("foo/**/*.txt");
         let path = String::from("foo/bar/baz/a.txt");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_11() {
         let pattern = String::from("/foo/bar");
         let path = String::from("foo/bar");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
     }
 
     #[test]
     fn test_glob_12() {
         let pattern = String::from("./foo/bar");
         let path = String::from("foo/bar");
-        assert_eq!(Glob::new(&pattern).is_match(&path), false);
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
+    }
+
+    #[test]
+    fn test_glob_13() {
+        let pattern = String::from("a*b*c");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"abc"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"axxbyyc"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"abbbcbc"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"axxcyyb"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"abcd"));
     }
 
+    #[test]
+    fn test_glob_14() {
+        let pattern = String::from("foo?.txt");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"foo1.txt"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"fooé.txt"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"foo.txt"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"foo12.txt"));
+    }
+
+    #[test]
+    fn test_glob_15() {
+        let pattern = String::from("file[0-9a].log");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"file7.log"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"filea.log"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"fileb.log"));
+        let pattern = String::from("file[!0-9].log");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"fileb.log"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"file7.log"));
+        let pattern = String::from("file[^0-9].log");
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"file7.log"));
+        let pattern = String::from("[]-]x");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"]x"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"-x"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"ax"));
+    }
+
+    #[test]
+    fn test_glob_16() {
+        let pattern = String::from("*.{rs,toml}");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"main.rs"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"Cargo.toml"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"Cargo.lock"));
+        let pattern = String::from("{src/{a,b},tests}/*.rs");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"src/a/x.rs"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"src/b/x.rs"));
+        assert!(Glob::new(&pattern).unwrap().is_match(&"tests/x.rs"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"src/c/x.rs"));
+        let pattern = String::from("{a}.txt");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"{a}.txt"));
+    }
+
+    #[test]
+    fn test_glob_17() {
+        let pattern = String::from("\\*star\\?");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"*star?"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"xstar?"));
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"*stars"));
+        let pattern = String::from("\\[a\\]\\{b,c\\}");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"[a]{b,c}"));
+        let pattern = String::from("[a");
+        assert!(Glob::new(&pattern).unwrap().is_match(&"[a"));
+    }
+
+    #[test]
+    fn test_glob_18() {
+        let pattern = String::from("foo/**/bar/c.txt");
+        let path = String::from("foo/bar");
+        assert!(!Glob::new(&pattern).unwrap().is_match(&path));
+        let pattern = String::from("src/*/*_test.rs");
+        assert!(
+            Glob::new(&pattern)
+                .unwrap()
+                .is_match(&"src/glob/glob_test.rs")
+        );
+        assert!(!Glob::new(&pattern).unwrap().is_match(&"src/glob_test.rs"));
+    }
+
+    #[test]
+    fn test_glob_19() {
+        let path = Path::new(OsStr::from_bytes(b"logs/caf\xe9.txt"));
+        assert!(Glob::new("logs/*.txt").unwrap().is_match(path));
+        assert!(Glob::new("logs/caf?.txt").unwrap().is_match(path));
+        assert!(Glob::new("logs/caf[!a-z].txt").unwrap().is_match(path));
+        assert!(!Glob::new("logs/caf[a-z].txt").unwrap().is_match(path));
+        assert!(!Glob::new("logs/café.txt").unwrap().is_match(path));
+        assert!(
+            Glob::new("logs/café.txt")
+                .unwrap()
+                .is_match("logs/café.txt")
+        );
+    }
+
+    #[test]
+    fn test_glob_20() {
+        assert!(Glob::new("file[z-a].log").is_err());
+        assert!(GlobSet::new(&["*.rs", "src/[9-0]/*"]).is_err());
+        let glob = Glob::new("*.{rs,toml").unwrap();
+        assert!(glob.is_match(&"main.{rs,toml"));
+        assert!(!glob.is_match(&"main.rs"));
+        let glob = Glob::new("foo\\").unwrap();
+        assert!(glob.is_match(&"foo\\"));
+        assert!(!glob.is_match(&"foo"));
+    }
+
+    #[test]
+    fn test_glob_set_1() {
+        let set = GlobSet::new(&["*.rs", "Cargo.toml", "*.tar.gz", "*_test.*"]).unwrap();
+        assert_eq!(set.matches(&"main.rs"), vec![0]);
+        assert_eq!(set.matches(&"Cargo.toml"), vec![1]);
+        assert_eq!(set.matches(&"logs.tar.gz"), vec![2]);
+        assert_eq!(set.matches(&"glob_test.rs"), vec![0, 3]);
+        assert_eq!(set.matches(&"main.rs.bak"), Vec::<usize>::new());
+        assert!(set.is_match(&".rs"));
+        assert!(!set.is_match(&"Cargo.lock"));
+        assert_eq!(set.position("*.rs"), Some(0));
+        assert_eq!(set.position("Cargo.toml"), Some(1));
+        assert_eq!(set.position("main.rs"), None);
+    }
+
+    #[test]
+    fn test_glob_set_2() {
+        let set = GlobSet::new(&["*.rs", "src/*.toml", "/Cargo.toml", "./docs/**"]).unwrap();
+        assert_eq!(set.matches(&"src/main.rs"), vec![0]);
+        assert_eq!(set.matches(&"./src/a/b.rs"), vec![0]);
+        assert_eq!(set.matches(&"src/x.toml"), vec![1]);
+        assert_eq!(set.matches(&"./src/x.toml"), vec![1]);
+        assert!(!set.is_match(&"lib/src/x.toml"));
+        assert_eq!(set.matches(&"Cargo.toml"), vec![2]);
+        assert!(!set.is_match(&"sub/Cargo.toml"));
+        assert_eq!(set.matches(&"docs/a/b/c.md"), vec![3]);
+        assert!(!set.is_match(&"src/docs/a.md"));
+        let path = Path::new(OsStr::from_bytes(b"src/\xff\xfe.rs"));
+        assert_eq!(set.matches(path), vec![0]);
+    }
+
+    #[test]
+    fn test_glob_set_3() {
+        let set = GlobSet::new(&["*.txt", "notes/*", "a b.md"]).unwrap();
+        let file_match = |filename: &str, relative: &str| {
+            set.file_match(Path::new(filename), Path::new(relative))
+        };
+        assert_eq!(file_match("/tmp/notes/x.txt", "notes/x.txt"), Some("*.txt"));
+        assert_eq!(file_match("/tmp/notes/x.md", "notes/x.md"), Some("notes/*"));
+        assert_eq!(file_match("a b.md", "a b.md"), Some("a b.md"));
+        assert_eq!(file_match("x.md", "x.md"), None);
+    }
+
+    #[test]
+    fn test_glob_set_4() {
+        let set = GlobSet::new(&["src/**/*.rs", "**/*.md", "a/**/b/**/c"]).unwrap();
+        assert_eq!(set.matches(&"src/main.rs"), vec![0]);
+        assert_eq!(set.matches(&"src/a/b/deep.rs"), vec![0]);
+        assert_eq!(set.matches(&"./src/a/b/c/d/deeper.rs"), vec![0]);
+        assert!(!set.is_match(&"lib/src/a/x.rs"));
+        assert_eq!(set.matches(&"README.md"), vec![1]);
+        assert_eq!(set.matches(&"docs/guide/intro.md"), vec![1]);
+        assert_eq!(set.matches(&"a/b/c"), vec![2]);
+        assert_eq!(set.matches(&"a/x/y/b/z/w/c"), vec![2]);
+        assert!(!set.is_match(&"a/x/y/c"));
+    }
 }
@@ -0,0 +1,403 @@
+use std::io::{self, Read};
+
+/// Window size of DEFLATE: the furthest back a match may refer to.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Base lengths and extra bits for length codes 257..=285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits for distance codes 0..=29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which code length code lengths are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid deflate data: {}", msg),
+    )
+}
+
+/// Reads a byte stream least significant bit first, as DEFLATE requires.
+struct BitReader<R: Read> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    bits: u64,
+    nbits: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; 32 * 1024].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            bits: 0,
+            nbits: 0,
+        }
+    }
+
+    /// The next byte straight from the input, ignoring buffered bits.
+    fn next_input_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pos == self.len {
+            self.len = loop {
+                match self.inner.read(&mut self.buf) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    res => break res?,
+                }
+            };
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.nbits < n {
+            let byte = self
+                .next_input_byte()?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            self.bits |= (byte as u64) << self.nbits;
+            self.nbits += 8;
+        }
+        let value = (self.bits & ((1u64 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.nbits -= n;
+        Ok(value)
+    }
+
+    /// Drops the bits left over in the current byte.
+    fn align(&mut self) {
+        let rest = self.nbits % 8;
+        self.bits >>= rest;
+        self.nbits -= rest;
+    }
+
+    /// The next whole byte, or `None` at the end of input. Only meaningful
+    /// after [`BitReader::align`].
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        if self.nbits >= 8 {
+            return self.bits(8).map(|b| Some(b as u8));
+        }
+        self.next_input_byte()
+    }
+}
+
+/// A canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    /// Number of codes of each length, index 0 unused.
+    counts: [u16; 16],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        // Reject over-subscribed codes; incomplete ones are allowed.
+        let mut left = 1i32;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, len) in lengths.iter().enumerate() {
+            if *len != 0 {
+                symbols[offsets[*len as usize] as usize] = symbol as u16;
+                offsets[*len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode<R: Read>(&self, reader: &mut BitReader<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let literals = Self::new(&lengths).expect("fixed literal code is valid");
+        let distances = Self::new(&[5; 30]).expect("fixed distance code is valid");
+        (literals, distances)
+    }
+}
+
+enum State {
+    BlockHeader,
+    Stored(usize),
+    Huffman(Box<(Huffman, Huffman)>),
+    Done,
+}
+
+/// A streaming decoder for raw DEFLATE data (RFC 1951).
+///
+/// Output is decoded on demand as it is read, keeping only the window
+/// needed for back references in memory.
+pub struct Inflater<R: Read> {
+    reader: BitReader<R>,
+    state: State,
+    last_block: bool,
+    /// Decoded output; everything before `out_pos` has been read already.
+    history: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<R: Read> Inflater<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            reader: BitReader::new(inner),
+            state: State::BlockHeader,
+            last_block: false,
+            history: Vec::new(),
+            out_pos: 0,
+        }
+    }
+
+    /// Prepares to decode a new DEFLATE stream following the current one in
+    /// the same input.
+    pub fn reset(&mut self) {
+        self.state = State::BlockHeader;
+        self.last_block = false;
+        self.history.clear();
+        self.out_pos = 0;
+    }
+
+    /// Reads a byte that follows the end of the DEFLATE stream, such as a
+    /// container trailer. Returns `None` at the end of input.
+    pub fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.reader.align();
+        self.reader.byte()
+    }
+
+    fn read_block_header(&mut self) -> io::Result<()> {
+        if self.last_block {
+            self.state = State::Done;
+            return Ok(());
+        }
+        self.last_block = self.reader.bits(1)? == 1;
+        self.state = match self.reader.bits(2)? {
+            0 => {
+                self.reader.align();
+                let len = self.reader.bits(16)?;
+                let nlen = self.reader.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid_data("stored block length mismatch"));
+                }
+                State::Stored(len as usize)
+            }
+            1 => State::Huffman(Box::new(Huffman::fixed())),
+            2 => State::Huffman(Box::new(self.read_dynamic_tables()?)),
+            _ => return Err(invalid_data("invalid block type")),
+        };
+        Ok(())
+    }
+
+    fn read_dynamic_tables(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let nlen = self.reader.bits(5)? as usize + 257;
+        let ndist = self.reader.bits(5)? as usize + 1;
+        let ncode = self.reader.bits(4)? as usize + 4;
+        if nlen > 286 || ndist > 30 {
+            return Err(invalid_data("too many length or distance codes"));
+        }
+        let mut code_lengths = [0u8; 19];
+        for i in CODE_LENGTH_ORDER.iter().take(ncode) {
+            code_lengths[*i] = self.reader.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+        let mut lengths = vec![0u8; nlen + ndist];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = code_lengths.decode(&mut self.reader)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => {
+                    if i == 0 {
+                        return Err(invalid_data("repeat with no previous length"));
+                    }
+                    (lengths[i - 1], 3 + self.reader.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.reader.bits(3)? as usize),
+                _ => (0, 11 + self.reader.bits(7)? as usize),
+            };
+            if i + repeat > lengths.len() {
+                return Err(invalid_data("too many code lengths"));
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid_data("missing end-of-block code"));
+        }
+        Ok((
+            Huffman::new(&lengths[..nlen])?,
+            Huffman::new(&lengths[nlen..])?,
+        ))
+    }
+
+    /// Decodes until at least `want` new bytes are available or the stream
+    /// ends.
+    fn decode(&mut self, want: usize) -> io::Result<()> {
+        let target = self.history.len() + want;
+        while self.history.len() < target {
+            match &mut self.state {
+                State::Done => break,
+                State::BlockHeader => self.read_block_header()?,
+                State::Stored(remaining) => {
+                    if *remaining == 0 {
+                        self.state = State::BlockHeader;
+                        continue;
+                    }
+                    *remaining -= 1;
+                    let byte = self
+                        .reader
+                        .byte()?
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                    self.history.push(byte);
+                }
+                State::Huffman(tables) => {
+                    let (literals, distances) = tables.as_ref();
+                    let symbol = literals.decode(&mut self.reader)?;
+                    if symbol < 256 {
+                        self.history.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        self.state = State::BlockHeader;
+                        continue;
+                    }
+                    let index = symbol as usize - 257;
+                    if index >= LENGTH_BASE.len() {
+                        return Err(invalid_data("invalid length code"));
+                    }
+                    let len = LENGTH_BASE[index] as usize
+                        + self.reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                    let index = distances.decode(&mut self.reader)? as usize;
+                    if index >= DIST_BASE.len() {
+                        return Err(invalid_data("invalid distance code"));
+                    }
+                    let dist = DIST_BASE[index] as usize
+                        + self.reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                    if dist > self.history.len() {
+                        return Err(invalid_data("distance too far back"));
+                    }
+                    let start = self.history.len() - dist;
+                    for i in 0..len {
+                        let byte = self.history[start + i];
+                        self.history.push(byte);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.history.len() {
+            // Keep only the window needed for back references.
+            if self.history.len() > 2 * WINDOW_SIZE {
+                self.history.drain(..self.history.len() - WINDOW_SIZE);
+                self.out_pos = self.history.len();
+            }
+            self.decode(buf.len().max(WINDOW_SIZE))?;
+        }
+        let n = buf.len().min(self.history.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.history[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        Inflater::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_inflate_stored() {
+        let data = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+        assert_eq!(inflate(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_inflate_fixed() {
+        // "hello hello hello\n", compressed with fixed Huffman codes.
+        let data = [
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00,
+        ];
+        assert_eq!(inflate(&data).unwrap(), b"hello hello hello\n");
+    }
+
+    #[test]
+    fn test_inflate_dynamic() {
+        let data = [
+            0x1d, 0xc7, 0xa1, 0x0d, 0x00, 0x00, 0x08, 0xc0, 0xb0, 0x57, 0x78, 0x0d, 0x31, 0x81,
+            0x01, 0xb3, 0xff, 0x43, 0x20, 0x55, 0x05, 0x18, 0xb0, 0x13, 0x15, 0xe9, 0x48, 0x6a,
+            0xf0, 0xdd, 0xcb, 0xfb, 0x02,
+        ];
+        assert_eq!(
+            inflate(&data).unwrap(),
+            b"eeeeoeetnaettteten aeioeteteteeteniteioe"
+        );
+    }
+
+    #[test]
+    fn test_inflate_bad_block_type() {
+        assert!(inflate(&[0x07]).is_err());
+        assert!(inflate(&[0x01, 0x05, 0x00, 0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+//! The search behind mygrep, for embedding it in other tools.
+//!
+//! A [`Searcher`] reads files or other input and splits it into lines, a
+//! [`Matcher`] decides which lines are selected, and a [`Sink`] receives the
+//! selected lines along with any context around them. A [`SearchConfig`]
+//! bundles all three with the options of the `mygrep` command line.
+
+pub mod archive;
+pub mod config;
+pub mod decompress;
+pub mod encoding;
+pub mod error;
+pub mod filter;
+pub mod glob;
+mod inflate;
+pub mod matcher;
+mod process;
+pub mod searcher;
+pub mod sink;
+pub mod types;
+
+pub use config::{ColorMode, OutputFormat, SearchConfig, SearchConfigBuilder};
+pub use error::MygrepError;
+pub use filter::{Decision, DeviceMode, FileFilter, SkipReason};
+pub use matcher::{Matcher, SubstringFinder};
+pub use searcher::{BinaryMode, MmapChoice, Searcher};
+pub use sink::{Sink, SinkLine};
+pub use types::{FileType, Types};
@@ -1,15 +1,19 @@
 use clap::{ArgAction, Parser};
-use std::boxed::Box;
+use mygrep::{
+    BinaryMode, ColorMode, Decision, DeviceMode, Matcher, MmapChoice, MygrepError, OutputFormat,
+    SearchConfig, Sink, SinkLine, Types, archive, encoding, filter, matcher,
+};
 use std::collections::HashMap;
-use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
-use std::io::{BufRead, BufReader, Lines};
-use std::iter::{Enumerate, Iterator};
-use std::os::unix::fs::FileTypeExt;
-use std::path::Path;
-use std::sync::mpsc;
+use std::io::{BufRead, Read, Write};
+use std::iter::Iterator;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, SystemTime};
 
-mod glob;
 mod thread_pool;
 
 /// mygrep searches for PATTERNS in each FILE
@@ -18,11 +22,14 @@ mod thread_pool;
 struct Args {
     // #[arg(short = 'p')]
     // pattern: Option<String>,
-    pattern: String,
+    /// the pattern to search for; none is taken with --type-list or --files,
+    /// where every positional argument is a FILE
+    #[arg(required_unless_present_any = ["type_list", "files"])]
+    pattern: Option<OsString>,
 
-    /// Search for PATTERN in each FILE
-    #[arg(required = true)]
-    file: Vec<String>,
+    /// Search for PATTERN in each FILE; '-' (or no FILE when not recursive)
+    /// reads standard input
+    file: Vec<PathBuf>,
 
     /// ignore case distinctions in patterns and data
     #[arg(short, long, action = ArgAction::SetTrue)]
@@ -44,6 +51,17 @@ struct Args {
     #[arg(short = 'n', long, action = ArgAction::SetTrue)]
     line_number: bool,
 
+    /// print the byte offset of each output line within its file, or within
+    /// the text searched after -z, --pre or unpacking an archive member;
+    /// files are not transcoded then, even if they start with a byte order
+    /// mark, and --encoding is not allowed
+    #[arg(short = 'b', long, action = ArgAction::SetTrue)]
+    byte_offset: bool,
+
+    /// print the column of the first match in each output line; implies -n
+    #[arg(long, action = ArgAction::SetTrue)]
+    column: bool,
+
     /// print filename with output lines
     #[arg(short = 'H', long, action = ArgAction::SetTrue)]
     with_filename: bool,
@@ -64,489 +82,1037 @@ struct Args {
     #[arg(short, long, action = ArgAction::SetTrue)]
     count: bool,
 
-    /// --include=GLOB; search only files that match GLOB (a file pattern)
+    /// --include=GLOB; search only files that match GLOB (a file pattern,
+    /// matched against the path below the searched directory if it contains a '/')
     #[arg(long, action = ArgAction::Append)]
     include: Option<Vec<String>>,
 
     /// --exclude=GLOB; skip files that match GLOB
     #[arg(long, action = ArgAction::Append)]
     exclude: Option<Vec<String>>,
+
+    /// -t TYPE; search only files of TYPE (see --type-list)
+    #[arg(short = 't', long = "type", value_name = "TYPE", action = ArgAction::Append)]
+    file_type: Option<Vec<String>>,
+
+    /// -T TYPE; skip files of TYPE
+    #[arg(short = 'T', long = "type-not", value_name = "TYPE", action = ArgAction::Append)]
+    file_type_not: Option<Vec<String>>,
+
+    /// --type-add=NAME:GLOB; add GLOB to the file type NAME, defining it if needed
+    #[arg(long, value_name = "NAME:GLOB", action = ArgAction::Append)]
+    type_add: Option<Vec<String>>,
+
+    /// print every file type and its globs, then exit
+    #[arg(long, action = ArgAction::SetTrue)]
+    type_list: bool,
+
+    /// print the files that would be searched, walking directories, without
+    /// searching them; takes no PATTERN, only FILEs
+    #[arg(long, action = ArgAction::SetTrue)]
+    files: bool,
+
+    /// skip files larger than SIZE bytes; SIZE may end in K, M or G
+    #[arg(long, value_name = "SIZE", value_parser = filter::parse_size)]
+    max_filesize: Option<u64>,
+
+    /// search only files modified within AGE, e.g. 90s, 30m, 12h, 2d or 1w
+    #[arg(long, value_name = "AGE", value_parser = filter::parse_age)]
+    newer_than: Option<Duration>,
+
+    /// search only files modified longer than AGE ago
+    #[arg(long, value_name = "AGE", value_parser = filter::parse_age)]
+    older_than: Option<Duration>,
+
+    /// search only files modified after FILE was
+    #[arg(long, value_name = "FILE")]
+    newer: Option<PathBuf>,
+
+    /// search only files owned by the user with the numeric id UID
+    #[arg(long, value_name = "UID")]
+    owner: Option<u32>,
+
+    /// search only files belonging to the group with the numeric id GID
+    #[arg(long, value_name = "GID")]
+    group: Option<u32>,
+
+    /// also search the files listed in FILE, one per line; '-' reads the
+    /// list from standard input
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// the names in --files-from end in a NUL byte rather than a newline,
+    /// as printed by find -print0
+    #[arg(short = '0', long, action = ArgAction::SetTrue, requires = "files_from")]
+    null_files: bool,
+
+    /// print a NUL byte after each file name instead of ':' or a newline
+    #[arg(short = 'Z', long, action = ArgAction::SetTrue)]
+    null: bool,
+
+    /// input and output lines end in a NUL byte instead of a newline; unlike
+    /// GNU grep there is no -z for this, as -z is --search-zip
+    #[arg(long, action = ArgAction::SetTrue)]
+    null_data: bool,
+
+    /// assume that binary files are TYPE
+    #[arg(long, value_parser = ["binary", "text", "without-match"], default_value = "binary")]
+    binary_files: String,
+
+    /// equivalent to --binary-files=text
+    #[arg(short = 'a', long, action = ArgAction::SetTrue)]
+    text: bool,
+
+    /// equivalent to --binary-files=without-match
+    #[arg(short = 'I', action = ArgAction::SetTrue)]
+    ignore_binary: bool,
+
+    /// use LABEL as the standard input file name prefix
+    #[arg(long, default_value = "(standard input)")]
+    label: String,
+
+    /// search in compressed files (gzip, bzip2, xz, zstd)
+    #[arg(short = 'z', long, action = ArgAction::SetTrue)]
+    search_zip: bool,
+
+    /// search each file inside .tar, .tar.gz and .zip archives; --include
+    /// and --exclude apply to the paths inside the archive
+    #[arg(long, action = ArgAction::SetTrue)]
+    search_archives: bool,
+
+    /// search the output of COMMAND FILE, fed FILE on its standard input,
+    /// instead of FILE itself
+    #[arg(long, value_name = "COMMAND")]
+    pre: Option<PathBuf>,
+
+    /// --pre-glob=GLOB; only preprocess files that match GLOB
+    #[arg(long, action = ArgAction::Append, requires = "pre")]
+    pre_glob: Option<Vec<String>>,
+
+    /// decode input from ENCODING (e.g. utf-16le, latin1, shift_jis)
+    /// instead of only honouring a byte order mark
+    #[arg(long, value_parser = encoding::parse_label)]
+    encoding: Option<&'static encoding_rs::Encoding>,
+
+    /// search regular files through a memory map; by default only files of
+    /// at least 1MiB are mapped
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "no_mmap")]
+    mmap: bool,
+
+    /// never memory map files
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "mmap")]
+    no_mmap: bool,
+
+    /// highlight matches, file names and line numbers; WHEN is never,
+    /// always or auto (only on a terminal), the default when WHEN is left out
+    #[arg(long, visible_alias = "colour", value_name = "WHEN", value_parser = ["never", "always", "auto"], default_value = "never", num_args = 0..=1, require_equals = true, default_missing_value = "auto")]
+    color: String,
+
+    /// print every match as FILE:LINE:COLUMN:TEXT, for Vim's :grep
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "emacs")]
+    vimgrep: bool,
+
+    /// print every match as FILE:LINE:COLUMN: TEXT, for Emacs' compilation mode
+    #[arg(long, action = ArgAction::SetTrue)]
+    emacs: bool,
+
+    /// explain on standard error why each file is searched or skipped
+    #[arg(long, visible_alias = "trace-filters", action = ArgAction::SetTrue)]
+    debug: bool,
 }
 
-#[derive(Debug)]
+impl Args {
+    /// The pattern and the files named on the command line. With --files
+    /// there is no pattern, so what would be taken for one is the first
+    /// file instead.
+    fn pattern_and_files(&self) -> (Option<&OsStr>, Vec<PathBuf>) {
+        if !self.files {
+            return (self.pattern.as_deref(), self.file.clone());
+        }
+        let files = self.pattern.iter().map(PathBuf::from);
+        (None, files.chain(self.file.iter().cloned()).collect())
+    }
+}
+
+#[derive(Debug, Default)]
 struct GrepData {
-    line_number: u32,
-    line: String,
-    filename: String,
+    line_number: u64,
+    /// The offset of the start of the line in the text searched, which is
+    /// its file unless that was decompressed or preprocessed.
+    byte_offset: u64,
+    /// The 1-based byte columns the matches in the line start at, when
+    /// they are printed: only the first, unless every match is printed.
+    columns: Vec<u64>,
+    line: Vec<u8>,
+    filename: PathBuf,
+    /// Set instead of `line` when the match is in a binary file.
+    binary: bool,
+}
+
+/// Prints `error` to standard error, unless -s asked for silence and
+/// --debug did not ask for every file to be accounted for.
+fn report(error: &MygrepError, config: &SearchConfig) {
+    if !config.no_messages() || config.debug() {
+        eprintln!("mygrep: {}", error);
+    }
+}
+
+/// With --debug, prints what is done with `path` and why to standard error.
+fn debug<D: fmt::Display>(config: &SearchConfig, path: &Path, what: D) {
+    if config.debug() {
+        eprintln!("mygrep: debug: {}: {}", path.display(), what);
+    }
 }
 
-impl std::default::Default for GrepData {
-    fn default() -> Self {
-        Self {
-            line_number: 0,
-            line: String::new(),
-            filename: String::new(),
+/// Tells whether `decision` lets `path` be searched, explaining why not.
+fn is_searched(decision: Decision, path: &Path, config: &SearchConfig) -> bool {
+    match decision {
+        Decision::Search => true,
+        Decision::Skip(reason) => {
+            debug(config, path, format_args!("skipped, {}", reason));
+            false
         }
     }
 }
 
-fn is_match(pattern: &String, line: &String) -> bool {
-    line.contains(pattern.as_str())
+/// `path` relative to the directory `root` it was found under.
+fn relative_to<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
 }
 
-fn is_case_insensitive_match(pattern: &String, line: &String) -> bool {
-    is_match(&pattern.to_lowercase(), &line.to_lowercase())
+/// Sends the lines a search selects in `filename` to the printer.
+struct ChannelSink<'a> {
+    filename: &'a Path,
+    config: &'a SearchConfig,
+    tx: &'a mpsc::Sender<GrepData>,
+    has_match: bool,
 }
 
-fn eprintln(msg: String, ok: bool) {
-    if ok {
-        eprintln!("{}", msg);
+impl Sink for ChannelSink<'_> {
+    fn matched(&mut self, line: &SinkLine<'_>) -> std::io::Result<bool> {
+        self.has_match = true;
+        if self.config.files_without_match() {
+            return Ok(false);
+        }
+        let every_match = self.config.format() != OutputFormat::Standard;
+        let columns = if self.config.column() || every_match {
+            matcher::match_columns(line.bytes, self.config.matcher(), every_match)
+        } else {
+            Vec::new()
+        };
+        let grep_data = GrepData {
+            line_number: line.line_number,
+            byte_offset: line.byte_offset,
+            columns,
+            line: line.bytes.to_vec(),
+            filename: self.filename.to_path_buf(),
+            binary: false,
+        };
+        let _ = self.tx.send(grep_data);
+        Ok(true)
+    }
+
+    fn binary_matched(&mut self, line_number: u64) -> std::io::Result<bool> {
+        self.has_match = true;
+        if self.config.files_without_match() {
+            return Ok(false);
+        }
+        let grep_data = GrepData {
+            line_number,
+            filename: self.filename.to_path_buf(),
+            binary: true,
+            ..Default::default()
+        };
+        let _ = self.tx.send(grep_data);
+        // Every match has to be seen to be counted.
+        Ok(self.config.count())
+    }
+
+    fn binary_skipped(&mut self, line_number: u64) -> std::io::Result<()> {
+        debug(
+            self.config,
+            self.filename,
+            format_args!("skipped, binary data in line {}", line_number),
+        );
+        Ok(())
     }
 }
 
-#[derive(Clone)]
-struct GrepState {
-    pattern: String,
-    ignore_case: bool,
-    invert_match: bool,
-    no_messages: bool,
-    max_count: u32,
-    show_line_number: bool,
-    with_filename: bool,
-    devices: String,
-    recursive: bool,
-    files_without_match: bool,
-    count: bool,
-    include: Option<Vec<String>>,
-    exclude: Option<Vec<String>>,
+/// Runs `search` with a sink reporting under `filename`, then reports any
+/// error, or `filename` itself when listing files without a match.
+fn search_with<F>(filename: &Path, config: &SearchConfig, tx: &mpsc::Sender<GrepData>, search: F)
+where
+    F: FnOnce(&mut ChannelSink) -> std::io::Result<()>,
+{
+    let mut sink = ChannelSink {
+        filename,
+        config,
+        tx,
+        has_match: false,
+    };
+    if let Err(e) = search(&mut sink) {
+        report(&MygrepError::io(filename, e), config);
+        return;
+    }
+    if !sink.has_match && config.files_without_match() {
+        let grep_data = GrepData {
+            filename: filename.to_path_buf(),
+            ..Default::default()
+        };
+        let _ = tx.send(grep_data);
+    }
 }
 
-struct GrepIterator<'a, B: BufRead> {
-    lines_iter: Enumerate<Lines<B>>,
-    grep_state: &'a GrepState,
-    filename: String,
+fn grep_file(filename: &Path, relative: &Path, config: &SearchConfig, tx: &mpsc::Sender<GrepData>) {
+    search_with(filename, config, tx, |sink| {
+        config
+            .searcher()
+            .search_path(config.matcher(), filename, relative, sink)
+    });
 }
 
-impl<'a, B: BufRead> GrepIterator<'a, B> {
-    fn new(e: Enumerate<Lines<B>>, grep_state: &'a GrepState, filename: String) -> Self {
-        GrepIterator {
-            lines_iter: e,
-            grep_state,
-            filename: filename.clone(),
+/// Searches the file `filename`, found as `relative` below the search
+/// root, or its members if it is an archive.
+fn grep_path(filename: &Path, relative: &Path, config: &SearchConfig, tx: &mpsc::Sender<GrepData>) {
+    match archive_kind(filename, config) {
+        Some(kind) => {
+            debug(config, filename, "searching the members of the archive");
+            grep_archive(filename, kind, config, tx);
+        }
+        None => {
+            debug(config, filename, "searching");
+            grep_file(filename, relative, config, tx);
         }
     }
 }
 
-impl<'a, B: BufRead> Iterator for GrepIterator<'a, B> {
-    // type Item = Enumerate<&'a String>::Item;
-    type Item = GrepData;
+/// SGR sequences used with --color, as in GNU grep's defaults.
+const COLOR_MATCH: &str = "\x1b[01;31m";
+const COLOR_FILENAME: &str = "\x1b[35m";
+const COLOR_LINE_NUMBER: &str = "\x1b[32m";
+const COLOR_SEPARATOR: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[m";
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let (i, line) = self.lines_iter.next()?;
-            if !line.is_ok() {
-                continue;
-            }
-            let line = line.unwrap();
-            let mut flag: bool;
-            if self.grep_state.ignore_case {
-                flag = is_case_insensitive_match(&self.grep_state.pattern, &line);
-            } else {
-                flag = is_match(&self.grep_state.pattern, &line);
-            }
-            if self.grep_state.invert_match {
-                flag = !flag;
-            }
-            if flag {
-                let grep_data = GrepData {
-                    line_number: (i + 1) as u32,
-                    line: line.clone(),
-                    filename: self.filename.clone(),
-                };
-                return Some(grep_data);
-            }
+/// Writes `bytes`, wrapped in the SGR sequence `sgr` if `color` is set.
+fn write_colored<W: Write>(
+    out: &mut W,
+    bytes: &[u8],
+    sgr: &str,
+    color: bool,
+) -> std::io::Result<()> {
+    if !color {
+        return out.write_all(bytes);
+    }
+    out.write_all(sgr.as_bytes())?;
+    out.write_all(bytes)?;
+    out.write_all(COLOR_RESET.as_bytes())
+}
+
+/// Writes `filename` as its raw bytes, so names that are not valid UTF-8 are
+/// printed unchanged.
+fn write_filename<W: Write>(out: &mut W, filename: &Path, color: bool) -> std::io::Result<()> {
+    write_colored(out, filename.as_os_str().as_bytes(), COLOR_FILENAME, color)
+}
+
+/// Writes `filename` followed by a ":", or by a NUL with -Z.
+fn write_filename_colon<W: Write>(
+    out: &mut W,
+    filename: &Path,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    write_filename(out, filename, color)?;
+    if config.null() {
+        out.write_all(b"\0")
+    } else {
+        write_colored(out, b":", COLOR_SEPARATOR, color)
+    }
+}
+
+/// Writes `filename` before a line of output: followed by ": ", or by a NUL
+/// with -Z.
+fn write_filename_prefix<W: Write>(
+    out: &mut W,
+    filename: &Path,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    write_filename_colon(out, filename, config, color)?;
+    if config.null() {
+        return Ok(());
+    }
+    write!(out, " ")
+}
+
+/// Exits quietly if writing output failed: stdout is gone (e.g. a closed
+/// pipe), so there is nothing left to report to.
+fn exit_if_stdout_gone(res: std::io::Result<()>) {
+    if res.is_err() {
+        std::process::exit(0);
+    }
+}
+
+/// Writes `filename` on a line of its own, or followed by a NUL with -Z.
+fn write_filename_line<W: Write>(
+    out: &mut W,
+    filename: &Path,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    write_filename(out, filename, color)?;
+    out.write_all(if config.null() { b"\0" } else { b"\n" })
+}
+
+/// Writes the ": " following a file name or line number.
+fn write_separator<W: Write>(out: &mut W, color: bool) -> std::io::Result<()> {
+    write_colored(out, b":", COLOR_SEPARATOR, color)?;
+    write!(out, " ")
+}
+
+/// Writes `line`, highlighting every match of `matcher` in it.
+fn write_highlighted<W: Write, M: Matcher + ?Sized>(
+    out: &mut W,
+    line: &[u8],
+    matcher: &M,
+) -> std::io::Result<()> {
+    let mut rest = line;
+    while let Some(m) = matcher.find(rest) {
+        if m.is_empty() {
+            break;
         }
+        out.write_all(&rest[..m.start])?;
+        write_colored(out, &rest[m.clone()], COLOR_MATCH, true)?;
+        rest = &rest[m.end..];
     }
+    out.write_all(rest)
 }
 
-fn grep_file<'a>(
-    filename: String,
-    grep_state: &'a GrepState,
-) -> Result<GrepIterator<'a, BufReader<fs::File>>, Box<dyn Error>> {
-    assert!(fs::exists(&filename).is_ok_and(|x| x));
-    let file = fs::File::open(&filename)?;
-    let reader = BufReader::new(file);
-    Ok(GrepIterator::new(
-        reader.lines().enumerate(),
-        &grep_state,
-        filename,
-    ))
+/// Whether `filename` names standard input rather than a file.
+fn is_stdin(filename: &Path) -> bool {
+    filename.as_os_str() == "-"
 }
 
-fn print_grep_data<'a>(grep_data: &GrepData, grep_state: &GrepState) {
-    if grep_state.files_without_match {
-        println!("{}", grep_data.filename);
-        return;
+/// The files searched when none are named: the working directory when it
+/// is walked, standard input otherwise.
+fn default_files(walk: bool) -> Vec<PathBuf> {
+    vec![PathBuf::from(if walk { "." } else { "-" })]
+}
+
+fn grep_stdin(config: &SearchConfig, tx: &mpsc::Sender<GrepData>) {
+    grep_reader(std::io::stdin().lock(), config, tx);
+}
+
+/// Searches `reader` as standard input, reported under --label.
+fn grep_reader<R: BufRead>(reader: R, config: &SearchConfig, tx: &mpsc::Sender<GrepData>) {
+    let name = PathBuf::from(config.label());
+    search_with(&name, config, tx, |sink| {
+        config
+            .searcher()
+            .search_reader(config.matcher(), reader, sink)
+    });
+}
+
+/// The kind of archive `filename` is, if archives are searched at all.
+fn archive_kind(filename: &Path, config: &SearchConfig) -> Option<archive::Kind> {
+    if !config.search_archives() {
+        return None;
     }
-    if grep_state.with_filename {
-        print!("{}: ", grep_data.filename);
+    archive::Kind::detect(filename)
+}
+
+/// Searches every member of the archive `filename` as a file of its own,
+/// reported as `archive:member`. --include and --exclude are matched
+/// against the member paths.
+fn grep_archive(
+    filename: &Path,
+    kind: archive::Kind,
+    config: &SearchConfig,
+    tx: &mpsc::Sender<GrepData>,
+) {
+    let res = archive::for_each_member(filename, kind, |member, reader| {
+        let name = archive::member_display_name(filename, member);
+        if !is_searched(config.filter().check_member(member), &name, config) {
+            return;
+        }
+        debug(config, &name, "searching");
+        search_with(&name, config, tx, |sink| {
+            config
+                .searcher()
+                .search_reader(config.matcher(), reader?, sink)
+        });
+    });
+    if let Err(e) = res {
+        report(&MygrepError::io(filename, e), config);
+    }
+}
+
+fn print_grep_data<W: Write>(
+    out: &mut W,
+    grep_data: &GrepData,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    if config.files_without_match() {
+        return write_filename_line(out, &grep_data.filename, config, color);
     }
-    if grep_state.show_line_number {
-        print!("{}: ", grep_data.line_number);
+    if grep_data.binary {
+        write!(out, "Binary file ")?;
+        write_filename(out, &grep_data.filename, false)?;
+        return writeln!(out, " matches");
     }
-    println!("{}", grep_data.line);
+    if config.format() != OutputFormat::Standard {
+        return print_quickfix(out, grep_data, config, color);
+    }
+    if config.with_filename() {
+        write_filename_prefix(out, &grep_data.filename, config, color)?;
+    }
+    if config.line_number() {
+        let line_number = grep_data.line_number.to_string();
+        write_colored(out, line_number.as_bytes(), COLOR_LINE_NUMBER, color)?;
+        write_separator(out, color)?;
+    }
+    if let Some(column) = grep_data.columns.first() {
+        let column = column.to_string();
+        write_colored(out, column.as_bytes(), COLOR_LINE_NUMBER, color)?;
+        write_separator(out, color)?;
+    }
+    if config.byte_offset() {
+        let byte_offset = grep_data.byte_offset.to_string();
+        write_colored(out, byte_offset.as_bytes(), COLOR_LINE_NUMBER, color)?;
+        write_separator(out, color)?;
+    }
+    write_line(out, grep_data, config, color)
+}
+
+/// Prints `grep_data` once for every match in it, in the form of
+/// `OutputFormat::Vimgrep` or `OutputFormat::Emacs`.
+fn print_quickfix<W: Write>(
+    out: &mut W,
+    grep_data: &GrepData,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    let line_number = grep_data.line_number.to_string();
+    for column in &grep_data.columns {
+        write_filename_colon(out, &grep_data.filename, config, color)?;
+        write_colored(out, line_number.as_bytes(), COLOR_LINE_NUMBER, color)?;
+        write_colored(out, b":", COLOR_SEPARATOR, color)?;
+        write_colored(out, column.to_string().as_bytes(), COLOR_LINE_NUMBER, color)?;
+        write_colored(out, b":", COLOR_SEPARATOR, color)?;
+        if config.format() == OutputFormat::Emacs {
+            write!(out, " ")?;
+        }
+        write_line(out, grep_data, config, color)?;
+    }
+    Ok(())
 }
 
+/// Writes the text of the line in `grep_data` and its terminator.
+fn write_line<W: Write>(
+    out: &mut W,
+    grep_data: &GrepData,
+    config: &SearchConfig,
+    color: bool,
+) -> std::io::Result<()> {
+    // Lines selected by -v hold no match to highlight.
+    if color && !config.invert_match() {
+        write_highlighted(out, &grep_data.line, config.matcher())?;
+    } else {
+        out.write_all(&grep_data.line)?;
+    }
+    // --null-data output is made of records just like its input.
+    out.write_all(if config.null_data() { b"\0" } else { b"\n" })
+}
+
+/// Walks a directory tree depth first, yielding the files in it that pass
+/// the filter. Errors reading a directory or an entry are yielded in place
+/// of the entry, so the walk carries on past them.
 struct GrepDirIterator<'a> {
-    stack: Vec<std::io::Result<fs::ReadDir>>,
-    grep_state: &'a GrepState,
+    root: PathBuf,
+    stack: Vec<(PathBuf, std::io::Result<fs::ReadDir>)>,
+    config: &'a SearchConfig,
 }
 
 impl<'a> GrepDirIterator<'a> {
-    fn new(dir_iter: std::io::Result<fs::ReadDir>, grep_state: &'a GrepState) -> Self {
+    fn new(dir: &Path, config: &'a SearchConfig) -> Self {
         GrepDirIterator {
-            stack: vec![dir_iter],
-            grep_state,
+            root: dir.to_path_buf(),
+            stack: vec![(dir.to_path_buf(), fs::read_dir(dir))],
+            config,
         }
     }
 }
 
 impl<'a> Iterator for GrepDirIterator<'a> {
-    type Item = Result<GrepIterator<'a, BufReader<fs::File>>, Box<dyn Error>>;
+    type Item = Result<PathBuf, MygrepError>;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let dir_iter_res = self.stack.pop()?;
-            if dir_iter_res.is_err() {
-                continue;
-            }
-
-            let mut dir_iter = dir_iter_res.unwrap();
-            let entry_op = dir_iter.next();
-            if entry_op.is_none() {
-                continue;
-            }
-            let entry_res = entry_op.unwrap();
-            if entry_res.is_err() {
-                self.stack.push(Ok(dir_iter));
-                continue;
-            }
-            let entry = entry_res.unwrap();
-            if entry.metadata().is_err() {
-                self.stack.push(Ok(dir_iter));
-                continue;
-            }
-            if entry.metadata().unwrap().is_dir() {
-                let dirname_res = entry.path().into_os_string().into_string();
-                self.stack.push(Ok(dir_iter));
-                if dirname_res.is_err() {
-                    continue;
+            let (dir, dir_iter) = self.stack.pop()?;
+            let mut dir_iter = match dir_iter {
+                Ok(dir_iter) => dir_iter,
+                Err(e) => return Some(Err(MygrepError::io(dir, e))),
+            };
+            let entry = match dir_iter.next() {
+                None => continue,
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    self.stack.push((dir.clone(), Ok(dir_iter)));
+                    return Some(Err(MygrepError::io(dir, e)));
                 }
-                let dirname = dirname_res.unwrap();
-                self.stack.push(fs::read_dir(&dirname));
+            };
+            self.stack.push((dir, Ok(dir_iter)));
+            let filename = entry.path();
+            // The entry may be gone by now; that is reported like any
+            // other file that cannot be read.
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => return Some(Err(MygrepError::io(filename, e))),
+            };
+            if metadata.is_dir() {
+                debug(self.config, &filename, "entering directory");
+                let dir_iter = fs::read_dir(&filename);
+                self.stack.push((filename, dir_iter));
                 continue;
-            } else {
-                let filename_res = entry.path().into_os_string().into_string();
-                self.stack.push(Ok(dir_iter));
-                if filename_res.is_err() {
-                    continue;
-                }
-                let filename = filename_res.unwrap();
-                let metadata_res = fs::metadata(&filename);
-                if metadata_res.is_err() {
-                    continue;
-                }
-                let file_type = metadata_res.unwrap().file_type();
-                if self.grep_state.devices == String::from("skip")
-                    && (file_type.is_block_device() || file_type.is_fifo() || file_type.is_socket())
-                {
-                    continue;
-                }
-                return Some(grep_file(filename.clone(), self.grep_state).map_err(|e| {
-                    std::io::Error::new(
-                        e.as_ref()
-                            .downcast_ref::<std::io::Error>()
-                            .map_or(std::io::ErrorKind::Other, |e| e.kind()),
-                        format!("{}: {}", filename, e),
-                    )
-                    .into()
-                }));
+            }
+            let metadata = match fs::metadata(&filename) {
+                Ok(metadata) => metadata,
+                Err(e) => return Some(Err(MygrepError::io(filename, e))),
+            };
+            let relative = relative_to(&self.root, &filename);
+            let decision = self.config.filter().check(&filename, relative, &metadata);
+            if is_searched(decision, &filename, self.config) {
+                return Some(Ok(filename));
             }
         }
     }
 }
 
-fn grep_dir<'a>(
-    filename: &String,
-    grep_state: &'a GrepState,
-) -> Result<GrepDirIterator<'a>, Box<dyn Error>> {
-    assert!(fs::exists(filename).is_ok_and(|x| x));
-    Ok(GrepDirIterator::new(fs::read_dir(filename), grep_state))
+/// Calls `f` with `filename` if the filter lets it be searched or, if it is
+/// a directory and `recursive` is set, with every such file below it. `f`
+/// is also given the path of the file relative to `filename`, or
+/// `filename` itself when it is not a directory.
+fn for_each_file<F: FnMut(&Path, &Path)>(
+    filename: &Path,
+    config: &SearchConfig,
+    recursive: bool,
+    mut f: F,
+) {
+    let metadata = match fs::metadata(filename) {
+        Ok(metadata) => metadata,
+        Err(e) => return report(&MygrepError::io(filename, e), config),
+    };
+    if !metadata.is_dir() {
+        let decision = config.filter().check(filename, filename, &metadata);
+        if is_searched(decision, filename, config) {
+            f(filename, filename);
+        }
+        return;
+    }
+    if !recursive {
+        let e = std::io::Error::new(std::io::ErrorKind::IsADirectory, "Is a directory");
+        return report(&MygrepError::io(filename, e), config);
+    }
+    debug(config, filename, "entering directory");
+    for name in GrepDirIterator::new(filename, config) {
+        match name {
+            Ok(name) => f(&name, relative_to(filename, &name)),
+            Err(e) => report(&e, config),
+        }
+    }
 }
 
-fn divide_files_by_workers(files: Vec<String>, n_workers: usize) -> Vec<Vec<String>> {
-    let mut result = Vec::new();
-    let mut collected_files = Vec::new();
-    let mut collected_dirs = Vec::new();
-    for file in files.iter() {
-        let metadata = fs::metadata(file).unwrap();
-        if metadata.is_dir() {
-            collected_dirs.push(file.clone());
-        } else {
-            collected_files.push(file.clone());
-        }
+/// Writes every file searching `files` would look at to `out`, without
+/// reading any. Directories are walked whether or not -r was given.
+fn list_files<W: Write>(
+    out: &mut W,
+    files: &[PathBuf],
+    config: &SearchConfig,
+) -> std::io::Result<()> {
+    let color = config.color().is_enabled();
+    let mut res = Ok(());
+    for filename in files.iter().filter(|f| !is_stdin(f)) {
+        for_each_file(filename, config, true, |name, _| {
+            if res.is_ok() {
+                res = write_filename_line(out, name, config, color);
+            }
+        });
     }
-    result.push(collected_files);
-    let n_workers = n_workers - 1;
-    let per_job = (collected_dirs.len() as i32 - 1) / n_workers as i32;
-    if per_job < 0 {
-        return result;
-    }
-    let per_job = per_job as usize;
-    for i in 0..n_workers {
-        let start = (per_job + 1) * i;
-        let end = start + (per_job + 1);
-        if end > collected_dirs.len() {
-            return result;
+    res
+}
+
+/// Splits `files` into jobs for `n_workers` threads: the first gets all the
+/// plain files, and the directories are dealt out among the rest.
+fn divide_files_by_workers(files: Vec<PathBuf>, n_workers: usize) -> Vec<Vec<PathBuf>> {
+    let mut result = vec![Vec::new(); n_workers.max(2)];
+    let mut next_dir_job = 0;
+    for file in files {
+        // Anything that cannot be looked at is left to the file job, which
+        // reports it.
+        if !is_stdin(&file) && fs::metadata(&file).is_ok_and(|m| m.is_dir()) {
+            result[1 + next_dir_job].push(file);
+            next_dir_job = (next_dir_job + 1) % (result.len() - 1);
+        } else {
+            result[0].push(file);
         }
-        result.push(
-            collected_dirs[start..end]
-                .iter()
-                .map(|item| item.clone())
-                .collect(),
-        );
     }
-    return result;
+    result.retain(|job| !job.is_empty());
+    result
+}
+
+/// Reads the names of files to search from `list`, or from standard input
+/// if it is "-", each ending in `terminator`. Empty names are left out.
+fn read_file_list(list: &Path, terminator: u8) -> Result<Vec<PathBuf>, MygrepError> {
+    let read = if is_stdin(list) {
+        let mut contents = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut contents)
+            .map(|_| contents)
+    } else {
+        fs::read(list)
+    };
+    let contents = read.map_err(|e| MygrepError::io(list, e))?;
+    Ok(contents
+        .split(|b| *b == terminator)
+        .filter(|name| !name.is_empty())
+        .map(|name| PathBuf::from(OsStr::from_bytes(name)))
+        .collect())
 }
 
 fn main() {
     let args = Args::parse();
-    let grep_state = GrepState {
-        pattern: args.pattern.clone(),
-        ignore_case: args.ignore_case,
-        invert_match: args.invert_match,
-        no_messages: args.no_messages,
-        max_count: args
-            .max_count
-            .map(|x| if x == 0 { u32::MAX } else { x })
-            .unwrap_or(u32::MAX),
-        show_line_number: args.line_number,
-        with_filename: args.with_filename,
-        devices: args.devices.clone(),
-        recursive: args.recursive,
-        files_without_match: args.files_without_match,
-        count: args.count,
-        include: args.include.clone(),
-        exclude: args.exclude.clone(),
+    let mut types = Types::new();
+    for spec in args.type_add.as_deref().unwrap_or_default() {
+        if let Err(e) = types.add(spec) {
+            eprintln!("mygrep: {}", e);
+            std::process::exit(2);
+        }
+    }
+    if args.type_list {
+        let mut out = std::io::stdout().lock();
+        for (name, globs) in types.iter() {
+            if writeln!(out, "{}: {}", name, globs.join(", ")).is_err() {
+                break;
+            }
+        }
+        return;
+    }
+    let newer = args.newer.as_ref().map(|file| {
+        match fs::metadata(file).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                eprintln!("mygrep: {}", MygrepError::io(file, e));
+                std::process::exit(2);
+            }
+        }
+    });
+    let now = SystemTime::now();
+    let ago = |age: Duration| now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+    let (pattern, mut files) = args.pattern_and_files();
+    let pattern = match pattern.map(OsStr::to_str) {
+        None => "",
+        Some(Some(pattern)) => pattern,
+        Some(None) => {
+            eprintln!("mygrep: the pattern is not valid UTF-8");
+            std::process::exit(2);
+        }
+    };
+    let config = SearchConfig::builder(pattern)
+        .ignore_case(args.ignore_case)
+        .invert_match(args.invert_match)
+        .no_messages(args.no_messages)
+        // -m 0 places no limit.
+        .max_count(args.max_count.filter(|n| *n != 0).map(u64::from))
+        .line_number(args.line_number || args.column)
+        .byte_offset(args.byte_offset)
+        .column(args.column)
+        .with_filename(args.with_filename)
+        .devices(match args.devices.as_str() {
+            "read" => DeviceMode::Read,
+            _ => DeviceMode::Skip,
+        })
+        .recursive(args.recursive)
+        .files_without_match(args.files_without_match)
+        .count(args.count)
+        .include(args.include.as_deref().unwrap_or_default())
+        .exclude(args.exclude.as_deref().unwrap_or_default())
+        .types(types)
+        .select_types(args.file_type.as_deref().unwrap_or_default())
+        .exclude_types(args.file_type_not.as_deref().unwrap_or_default())
+        .max_filesize(args.max_filesize)
+        .modified_after(args.newer_than.map(ago).into_iter().chain(newer).max())
+        .modified_before(args.older_than.map(ago))
+        .owner(args.owner)
+        .group(args.group)
+        .binary(if args.text {
+            BinaryMode::Text
+        } else if args.ignore_binary {
+            BinaryMode::WithoutMatch
+        } else {
+            match args.binary_files.as_str() {
+                "text" => BinaryMode::Text,
+                "without-match" => BinaryMode::WithoutMatch,
+                _ => BinaryMode::Binary,
+            }
+        })
+        .label(&args.label)
+        .decompress(args.search_zip)
+        .search_archives(args.search_archives)
+        .preprocessor(args.pre.clone())
+        .preprocessor_glob(args.pre_glob.as_deref().unwrap_or_default())
+        .encoding(args.encoding)
+        .mmap(if args.mmap {
+            MmapChoice::Always
+        } else if args.no_mmap {
+            MmapChoice::Never
+        } else {
+            MmapChoice::Auto
+        })
+        .color(match args.color.as_str() {
+            "always" => ColorMode::Always,
+            "auto" => ColorMode::Auto,
+            _ => ColorMode::Never,
+        })
+        .debug(args.debug)
+        .null(args.null)
+        .null_data(args.null_data)
+        .format(if args.vimgrep {
+            OutputFormat::Vimgrep
+        } else if args.emacs {
+            OutputFormat::Emacs
+        } else {
+            OutputFormat::Standard
+        })
+        .build();
+    let config = match config {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("mygrep: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(list) = &args.files_from {
+        let terminator = if args.null_files { b'\0' } else { b'\n' };
+        match read_file_list(list, terminator) {
+            Ok(listed) => files.extend(listed),
+            Err(e) => {
+                eprintln!("mygrep: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    let files = if !files.is_empty() || args.files_from.is_some() {
+        files
+    } else {
+        default_files(args.recursive || args.files)
     };
-    let grep_state_clone = grep_state.clone();
+    if args.files {
+        exit_if_stdout_gone(list_files(&mut std::io::stdout().lock(), &files, &config));
+        return;
+    }
 
     let n_workers = 4;
-    let jobs = divide_files_by_workers(args.file.clone(), n_workers);
+    let jobs = divide_files_by_workers(files, n_workers);
     let mut pool = thread_pool::ThreadPool::new(n_workers);
 
     let (tx, rx) = mpsc::channel();
     for job in jobs {
         let tx = tx.clone();
-        let grep_state = grep_state.clone();
+        let config = config.clone();
         pool.execute(move || {
             for filename in job {
-                let metadata_res = fs::metadata(&filename);
-                if metadata_res.is_err() {
-                    eprintln(
-                        format!("{}", metadata_res.unwrap_err()),
-                        !grep_state.no_messages,
-                    );
+                if is_stdin(&filename) {
+                    grep_stdin(&config, &tx);
                     continue;
                 }
-                let metadata = metadata_res.unwrap().file_type();
-                if metadata.is_dir() {
-                    if grep_state_clone.recursive == false {
-                        eprintln(
-                            format!("mygrep: {}: Is a directory", filename),
-                            !grep_state_clone.no_messages,
-                        );
-                        continue;
-                    }
-                    match grep_dir(&filename, &grep_state) {
-                        Err(e) => eprintln(format!("{}", e), !grep_state.no_messages),
-                        Ok(dir_iter) => {
-                            for file_res in dir_iter {
-                                if file_res.is_err() {
-                                    eprintln(
-                                        format!("mygrep: {}", file_res.err().unwrap()),
-                                        !grep_state.no_messages,
-                                    );
-                                    continue;
-                                }
-                                let file = file_res.unwrap();
-                                let name = file.filename.clone();
-                                let m = fs::metadata(&name).unwrap().file_type();
-                                if grep_state.include.is_some() {
-                                    let mut has_match = false;
-                                    let globs = grep_state.include.as_ref().unwrap();
-                                    for g in globs {
-                                        if *g == name {
-                                            has_match = true;
-                                            break;
-                                        }
-                                        let basename_res = Path::new(&name).file_name();
-                                        if basename_res.is_none() {
-                                            continue;
-                                        }
-                                        let basename = basename_res.unwrap();
-                                        // TODO: do something other than using unwrap
-                                        let basename_str = basename.to_str().unwrap();
-                                        if glob::Glob::new(g).is_match(&basename_str) {
-                                            has_match = true;
-                                            break;
-                                        }
-                                    }
-                                    if !has_match {
-                                        continue;
-                                    }
-                                }
-                                if grep_state.exclude.is_some() {
-                                    let mut has_match = false;
-                                    let globs = grep_state.exclude.as_ref().unwrap();
-                                    for g in globs {
-                                        if *g == name {
-                                            has_match = true;
-                                            break;
-                                        }
-                                        let basename_res = Path::new(&name).file_name();
-                                        if basename_res.is_none() {
-                                            continue;
-                                        }
-                                        let basename = basename_res.unwrap();
-                                        // TODO: do something other than using unwrap
-                                        let basename_str = basename.to_str().unwrap();
-                                        if glob::Glob::new(g).is_match(&basename_str) {
-                                            has_match = true;
-                                            break;
-                                        }
-                                    }
-                                    if has_match {
-                                        continue;
-                                    }
-                                }
-                                if grep_state.devices == String::from("skip")
-                                    && (m.is_block_device() || m.is_fifo() || m.is_socket())
-                                {
-                                    continue;
-                                }
-                                let mut has_match = false;
-                                for grep_data in file {
-                                    has_match = true;
-                                    if grep_state.files_without_match {
-                                        break;
-                                    }
-                                    let _ = tx.send(grep_data);
-                                }
-                                if !has_match && grep_state.files_without_match {
-                                    let mut grep_data = GrepData::default();
-                                    grep_data.filename = name.clone();
-                                    let _ = tx.send(grep_data);
-                                }
-                            }
-                        }
-                    }
-                } else if grep_state.devices == String::from("skip") && !metadata.is_file() {
-                    continue;
-                } else if metadata.is_file()
-                    || metadata.is_block_device()
-                    || metadata.is_fifo()
-                    || metadata.is_socket()
-                {
-                    if grep_state.include.is_some() {
-                        let mut has_match = false;
-                        let globs = grep_state.include.as_ref().unwrap();
-                        for g in globs {
-                            if *g == *filename {
-                                has_match = true;
-                                break;
-                            }
-                            let basename_res = Path::new(&filename).file_name();
-                            if basename_res.is_none() {
-                                continue;
-                            }
-                            let basename = basename_res.unwrap();
-                            // TODO: do something other than using unwrap
-                            let basename_str = basename.to_str().unwrap();
-                            if glob::Glob::new(g).is_match(&basename_str) {
-                                has_match = true;
-                                break;
-                            }
-                        }
-                        if !has_match {
-                            continue;
-                        }
-                    }
-                    if grep_state.exclude.is_some() {
-                        let mut has_match = false;
-                        let globs = grep_state.exclude.as_ref().unwrap();
-                        for g in globs {
-                            if *g == *filename {
-                                has_match = true;
-                                break;
-                            }
-                            let basename_res = Path::new(&filename).file_name();
-                            if basename_res.is_none() {
-                                continue;
-                            }
-                            let basename = basename_res.unwrap();
-                            // TODO: do something other than using unwrap
-                            let basename_str = basename.to_str().unwrap();
-                            if glob::Glob::new(g).is_match(&basename_str) {
-                                has_match = true;
-                                break;
-                            }
-                        }
-                        if has_match {
-                            continue;
-                        }
-                    }
-                    match grep_file(filename.clone(), &grep_state) {
-                        Err(e) => eprintln(format!("{}", e), !grep_state.no_messages),
-                        Ok(iterator) => {
-                            let mut has_match = false;
-                            for grep_data in iterator {
-                                has_match = true;
-                                if grep_state.files_without_match {
-                                    break;
-                                }
-                                let _ = tx.send(grep_data);
-                            }
-                            if !has_match && grep_state.files_without_match {
-                                let mut grep_data = GrepData::default();
-                                grep_data.filename = filename.clone();
-                                let _ = tx.send(grep_data);
-                            }
-                        }
-                    }
-                }
+                for_each_file(&filename, &config, config.recursive(), |name, relative| {
+                    grep_path(name, relative, &config, &tx)
+                });
             }
         });
     }
     drop(tx);
-    let mut map: HashMap<String, usize> = HashMap::new();
-    rx.iter()
-        .take(grep_state_clone.max_count as usize)
-        .for_each(|grep_data| {
-            if grep_state_clone.count {
-                map.entry(grep_data.filename.clone())
-                    .and_modify(|x| *x += 1)
-                    .or_insert(1);
-            } else {
-                print_grep_data(&grep_data, &grep_state_clone);
-            }
-        });
-    if grep_state_clone.count {
+    let mut map: HashMap<PathBuf, usize> = HashMap::new();
+    let mut out = std::io::stdout().lock();
+    let max_count = config.max_count().map_or(usize::MAX, |n| n as usize);
+    let color = config.color().is_enabled();
+    for grep_data in rx.iter().take(max_count) {
+        if config.count() {
+            map.entry(grep_data.filename.clone())
+                .and_modify(|x| *x += 1)
+                .or_insert(1);
+        } else {
+            exit_if_stdout_gone(print_grep_data(&mut out, &grep_data, &config, color));
+        }
+    }
+    if config.count() {
         for (filename, v) in map.iter() {
-            if grep_state_clone.with_filename {
-                print!("{}: ", filename);
+            if config.with_filename() {
+                let _ = write_filename_prefix(&mut out, filename, &config, color);
             }
-            println!("{}", v);
+            let _ = writeln!(out, "{}", v);
         }
     }
+    let _ = out.flush();
     pool.join();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mygrep::SearchConfigBuilder;
+
+    /// Everything `search` sends to the printer.
+    fn sent<F: FnOnce(&mpsc::Sender<GrepData>)>(search: F) -> Vec<GrepData> {
+        let (tx, rx) = mpsc::channel();
+        search(&tx);
+        drop(tx);
+        rx.iter().collect()
+    }
+
+    #[test]
+    fn test_stdin() {
+        assert!(is_stdin(&default_files(false)[0]));
+        assert!(!is_stdin(&default_files(true)[0]));
+        let input = std::io::Cursor::new(b"one\ntwo\nthree\n".to_vec());
+        let config = SearchConfig::builder("t").build().unwrap();
+        let data = sent(|tx| grep_reader(input.clone(), &config, tx));
+        assert_eq!(data.len(), 2);
+        assert!(
+            data.iter()
+                .all(|d| d.filename == Path::new("(standard input)"))
+        );
+        assert_eq!((data[0].line_number, data[1].line_number), (2, 3));
+        let config = SearchConfig::builder("one")
+            .label("in.txt")
+            .build()
+            .unwrap();
+        let data = sent(|tx| grep_reader(input, &config, tx));
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].filename, Path::new("in.txt"));
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let list = std::env::temp_dir().join(format!("mygrep-list-{}", std::process::id()));
+        fs::write(&list, "a.txt\n\nsub/b c.txt\nlast.txt").unwrap();
+        let names = read_file_list(&list, b'\n').unwrap();
+        assert_eq!(
+            names,
+            ["a.txt", "sub/b c.txt", "last.txt"].map(PathBuf::from)
+        );
+        fs::write(&list, b"new\nline.txt\0\0a.txt\0tail \xff.txt").unwrap();
+        let names = read_file_list(&list, b'\0').unwrap();
+        let tail = PathBuf::from(OsStr::from_bytes(b"tail \xff.txt"));
+        assert_eq!(
+            names,
+            [PathBuf::from("new\nline.txt"), PathBuf::from("a.txt"), tail]
+        );
+        fs::remove_file(&list).unwrap();
+        let error = read_file_list(&list, b'\n').unwrap_err();
+        assert_eq!(error.path(), Some(list.as_path()));
+    }
+
+    #[test]
+    fn test_files_takes_no_pattern() {
+        let parse = |args: &[&str]| Args::try_parse_from(args).unwrap();
+        let args = parse(&["mygrep", "x", "a.txt"]);
+        let (pattern, files) = args.pattern_and_files();
+        assert_eq!(pattern, Some(OsStr::new("x")));
+        assert_eq!(files, [PathBuf::from("a.txt")]);
+        let args = parse(&["mygrep", "--files", "src", "a.txt"]);
+        let (pattern, files) = args.pattern_and_files();
+        assert_eq!(pattern, None);
+        assert_eq!(files, ["src", "a.txt"].map(PathBuf::from));
+        let args = parse(&["mygrep", "--files"]);
+        assert_eq!(args.pattern_and_files(), (None, Vec::new()));
+        assert!(Args::try_parse_from(["mygrep"]).is_err());
+    }
+
+    #[test]
+    fn test_list_files() {
+        let dir = std::env::temp_dir().join(format!("mygrep-files-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["a.rs", "b.txt", "sub/c.rs"] {
+            fs::write(dir.join(name), "x\n").unwrap();
+        }
+        let config = SearchConfig::builder("")
+            .include(&[String::from("*.rs")])
+            .build()
+            .unwrap();
+        let mut out = Vec::new();
+        let files = [dir.clone(), PathBuf::from("-")];
+        list_files(&mut out, &files, &config).unwrap();
+        let mut listed = out.split(|b| *b == b'\n').collect::<Vec<_>>();
+        listed.sort();
+        let name = |file: &str| dir.join(file).as_os_str().as_bytes().to_vec();
+        assert_eq!(listed, [&b""[..], &name("a.rs"), &name("sub/c.rs")]);
+        let config = SearchConfig::builder("").null(true).build().unwrap();
+        let mut out = Vec::new();
+        list_files(&mut out, &[dir.join("b.txt")], &config).unwrap();
+        assert_eq!(out, [name("b.txt"), vec![0]].concat());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// What printing the search of `input` writes.
+    fn printed(config: SearchConfigBuilder, input: &str) -> String {
+        let input = std::io::Cursor::new(input.as_bytes().to_vec());
+        let config = config.label("f").build().unwrap();
+        let mut out = Vec::new();
+        for grep_data in sent(|tx| grep_reader(input, &config, tx)) {
+            print_grep_data(&mut out, &grep_data, &config, false).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_quickfix() {
+        let input = "ab xab\nno\nxx ab\n";
+        let vimgrep = || SearchConfig::builder("ab").format(OutputFormat::Vimgrep);
+        assert_eq!(
+            printed(vimgrep(), input),
+            "f:1:1:ab xab\nf:1:5:ab xab\nf:3:4:xx ab\n"
+        );
+        let emacs = SearchConfig::builder("ab").format(OutputFormat::Emacs);
+        assert_eq!(
+            printed(emacs, input),
+            "f:1:1: ab xab\nf:1:5: ab xab\nf:3:4: xx ab\n"
+        );
+        assert_eq!(printed(vimgrep().invert_match(true), input), "f:2:1:no\n");
+        assert_eq!(printed(vimgrep().null(true), "xx ab\n"), "f\x001:4:xx ab\n");
+        assert_eq!(
+            printed(
+                SearchConfig::builder("ab").with_filename(true).null(true),
+                "ab\n"
+            ),
+            "f\x00ab\n"
+        );
+    }
+
+    #[test]
+    fn test_pre_glob_below_root() {
+        let dir = std::env::temp_dir().join(format!("mygrep-pre-glob-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let upcase = dir.join("upcase");
+        fs::write(&upcase, "#!/bin/sh\ntr a-z A-Z\n").unwrap();
+        fs::set_permissions(&upcase, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        for name in ["a.txt", "sub/b.txt", "sub/c.md"] {
+            fs::write(dir.join(name), "plain\n").unwrap();
+        }
+        let config = SearchConfig::builder("PLAIN")
+            .include(&[String::from("*.txt"), String::from("*.md")])
+            .preprocessor(Some(upcase))
+            .preprocessor_glob(&[String::from("sub/*.txt")])
+            .build()
+            .unwrap();
+        let data = sent(|tx| {
+            for_each_file(&dir, &config, true, |name, relative| {
+                grep_path(name, relative, &config, tx)
+            })
+        });
+        let found = data.iter().map(|d| d.filename.clone()).collect::<Vec<_>>();
+        assert_eq!(found, [dir.join("sub/b.txt")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
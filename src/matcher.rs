@@ -0,0 +1,228 @@
+use memchr::memmem;
+use std::ops::Range;
+
+/// Finds matches of a pattern in bytes.
+pub trait Matcher {
+    /// The span of the first match in `haystack`, if any.
+    fn find(&self, haystack: &[u8]) -> Option<Range<usize>>;
+
+    /// Whether `haystack` holds a match.
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// Whether `find` over a buffer of many lines never misses a line with
+    /// a match, so that a searcher may skip every line in front of the first
+    /// hit instead of trying them one by one. A match found this way is
+    /// only a candidate; its line is still checked with `is_match`.
+    fn can_search_buffer(&self) -> bool {
+        false
+    }
+}
+
+/// The 1-based columns the matches of `matcher` in `line` start at: all of
+/// them, or only the first. A line without a match, selected by -v, is
+/// taken to have one at its start.
+pub fn match_columns<M: Matcher + ?Sized>(line: &[u8], matcher: &M, all: bool) -> Vec<u64> {
+    let mut columns = Vec::new();
+    let mut start = 0;
+    while let Some(m) = matcher.find(&line[start..]) {
+        columns.push((start + m.start) as u64 + 1);
+        if !all || m.is_empty() {
+            break;
+        }
+        start += m.end;
+    }
+    if columns.is_empty() {
+        columns.push(1);
+    }
+    columns
+}
+
+/// Letters roughly from most to least common in text. Bytes that are not
+/// listed are treated as rarer than any of them, except for a space.
+const LETTER_FREQUENCY: &[u8] = b"etaoinshrdlcumwfgypbvkjxqz";
+
+/// How common `byte` is expected to be; lower is rarer.
+fn frequency(byte: u8) -> usize {
+    if byte == b' ' {
+        return LETTER_FREQUENCY.len() + 1;
+    }
+    let lower = byte.to_ascii_lowercase();
+    LETTER_FREQUENCY
+        .iter()
+        .position(|b| *b == lower)
+        .map_or(0, |i| LETTER_FREQUENCY.len() - i)
+}
+
+enum Kind {
+    /// A byte for byte search.
+    Exact(Box<memmem::Finder<'static>>),
+    /// An ASCII pattern, lowercased, compared ignoring ASCII case. `rare` is
+    /// the index of the byte looked for first.
+    AsciiCaseless { pattern: Vec<u8>, rare: usize },
+    /// A pattern with non-ASCII letters, lowercased. Case folding those is
+    /// only defined for text, so haystacks are lowercased as UTF-8 and
+    /// invalid bytes fold to U+FFFD.
+    Unicode(String),
+}
+
+/// Searches for a fixed string, optionally ignoring case. The pattern is
+/// prepared once and then shared by every search.
+pub struct SubstringFinder {
+    kind: Kind,
+}
+
+impl SubstringFinder {
+    pub fn new(pattern: &str, ignore_case: bool) -> Self {
+        let has_case = pattern
+            .chars()
+            .any(|c| c.is_lowercase() || c.is_uppercase());
+        let kind = if !ignore_case || !has_case {
+            Kind::Exact(Box::new(
+                memmem::Finder::new(pattern.as_bytes()).into_owned(),
+            ))
+        } else if pattern.is_ascii() {
+            let pattern = pattern.to_ascii_lowercase().into_bytes();
+            let rare = (0..pattern.len())
+                .min_by_key(|i| frequency(pattern[*i]))
+                .unwrap_or(0);
+            Kind::AsciiCaseless { pattern, rare }
+        } else {
+            Kind::Unicode(pattern.chars().flat_map(fold_case).collect())
+        };
+        Self { kind }
+    }
+}
+
+impl Matcher for SubstringFinder {
+    fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        match &self.kind {
+            Kind::Exact(finder) => {
+                let start = finder.find(haystack)?;
+                Some(start..start + finder.needle().len())
+            }
+            Kind::AsciiCaseless { pattern, rare } => {
+                let start = find_ascii_caseless(pattern, *rare, haystack)?;
+                Some(start..start + pattern.len())
+            }
+            Kind::Unicode(pattern) => {
+                let (lowered, spans) = lowercase_with_spans(haystack);
+                let start = memmem::find(lowered.as_bytes(), pattern.as_bytes())?;
+                Some(spans[start].0..spans[start + pattern.len() - 1].1)
+            }
+        }
+    }
+
+    /// A non-ASCII pattern ignoring case can only be matched line by line.
+    fn can_search_buffer(&self) -> bool {
+        !matches!(self.kind, Kind::Unicode(_))
+    }
+}
+
+/// Lowercases `c` for comparing without case. The pattern and the haystack
+/// are both folded a character at a time, rather than with
+/// `str::to_lowercase`, whose final sigma depends on the characters around
+/// it; that final sigma is folded to the ordinary one instead.
+fn fold_case(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase().map(|c| if c == 'ς' { 'σ' } else { c })
+}
+
+/// Lowercases `haystack` as UTF-8, along with the span of the character in
+/// `haystack` that each byte of the result came from.
+fn lowercase_with_spans(haystack: &[u8]) -> (String, Vec<(usize, usize)>) {
+    let mut lowered = String::with_capacity(haystack.len());
+    let mut spans = Vec::with_capacity(haystack.len());
+    let mut offset = 0;
+    for chunk in haystack.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            let span = (offset, offset + c.len_utf8());
+            for lower in fold_case(c) {
+                lowered.push(lower);
+                spans.extend(std::iter::repeat_n(span, lower.len_utf8()));
+            }
+            offset = span.1;
+        }
+        if !chunk.invalid().is_empty() {
+            let span = (offset, offset + chunk.invalid().len());
+            lowered.push(char::REPLACEMENT_CHARACTER);
+            spans.extend(std::iter::repeat_n(span, 3));
+            offset = span.1;
+        }
+    }
+    (lowered, spans)
+}
+
+/// Finds the lowercase `pattern` in `haystack` ignoring ASCII case, by
+/// jumping between occurrences of its rare byte, in either case, and only
+/// then comparing the bytes around it.
+fn find_ascii_caseless(pattern: &[u8], rare: usize, haystack: &[u8]) -> Option<usize> {
+    let byte = pattern[rare];
+    let mut pos = rare;
+    while pos < haystack.len() {
+        let hit = pos + memchr::memchr2(byte, byte.to_ascii_uppercase(), &haystack[pos..])?;
+        let start = hit - rare;
+        if haystack
+            .get(start..start + pattern.len())
+            .is_some_and(|window| window.eq_ignore_ascii_case(pattern))
+        {
+            return Some(start);
+        }
+        pos = hit + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_columns() {
+        let finder = SubstringFinder::new("ab", false);
+        assert_eq!(match_columns(b"xab ab abab", &finder, false), [2]);
+        assert_eq!(match_columns(b"xab ab abab", &finder, true), [2, 5, 8, 10]);
+        assert_eq!(match_columns("é ab".as_bytes(), &finder, true), [4]);
+        assert_eq!(match_columns(b"no match", &finder, true), [1]);
+        let empty = SubstringFinder::new("", false);
+        assert_eq!(match_columns(b"abc", &empty, true), [1]);
+        assert_eq!(match_columns(b"", &empty, true), [1]);
+    }
+
+    #[test]
+    fn test_exact() {
+        let finder = SubstringFinder::new("needle", false);
+        assert_eq!(finder.find(b"hay needle hay"), Some(4..10));
+        assert_eq!(finder.find(b"hay NEEDLE hay"), None);
+        assert_eq!(SubstringFinder::new("", false).find(b"abc"), Some(0..0));
+    }
+
+    #[test]
+    fn test_ascii_caseless() {
+        let finder = SubstringFinder::new("NeEdLe", true);
+        assert!(finder.can_search_buffer());
+        assert_eq!(finder.find(b"hay nEEDLe hay"), Some(4..10));
+        assert_eq!(finder.find(b"needl needle"), Some(6..12));
+        assert_eq!(finder.find(b"eedle"), None);
+        assert_eq!(SubstringFinder::new("q", true).find(b"Q"), Some(0..1));
+        assert_eq!(SubstringFinder::new("", true).find(b"abc"), Some(0..0));
+        assert_eq!(SubstringFinder::new("1-2", true).find(b"a 1-2"), Some(2..5));
+    }
+
+    #[test]
+    fn test_unicode_caseless() {
+        let finder = SubstringFinder::new("ÉTÉ", true);
+        assert!(!finder.can_search_buffer());
+        assert!(finder.is_match("un été chaud".as_bytes()));
+        assert!(!finder.is_match(b"ete"));
+        assert!(!finder.is_match(b"\xff\xfe"));
+        // Spans are of the original bytes, not of the lowercased ones.
+        assert_eq!(finder.find(b"\xff\xc3\x89t\xc3\x89!"), Some(1..6));
+        let finder = SubstringFinder::new("i\u{307}x", true);
+        assert_eq!(finder.find("aİX".as_bytes()), Some(1..4));
+        let finder = SubstringFinder::new("ΣΑΣ", true);
+        assert!(finder.is_match("ΣΑΣ".as_bytes()));
+        assert!(finder.is_match("σας".as_bytes()));
+        assert!(SubstringFinder::new("σας", true).is_match("ΣΑΣ".as_bytes()));
+    }
+}
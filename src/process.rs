@@ -0,0 +1,125 @@
+use crate::error;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// Reads the standard output of a child process. Once the output is
+/// exhausted the child is waited for, and a failed exit status is turned
+/// into an error carrying what the child wrote to standard error.
+pub struct CommandReader {
+    name: String,
+    child: Child,
+    stdout: ChildStdout,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    done: bool,
+}
+
+impl CommandReader {
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        let name = command.get_program().to_string_lossy().into_owned();
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, error::describe(&e))))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        // Drain stderr on the side so a chatty child cannot block on it.
+        let mut stderr_pipe = child.stderr.take().expect("stderr is piped");
+        let stderr = thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stderr_pipe.read_to_end(&mut buf);
+            buf
+        });
+        Ok(Self {
+            name,
+            child,
+            stdout,
+            stderr: Some(stderr),
+            done: false,
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.done = true;
+        let status = self.child.wait()?;
+        let stderr = self
+            .stderr
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        if status.success() {
+            return Ok(());
+        }
+        // Only the first line, to keep the diagnostic on a single line.
+        let stderr = String::from_utf8_lossy(&stderr);
+        let message = match stderr.lines().map(str::trim).find(|l| !l.is_empty()) {
+            None => format!("{} exited with {}", self.name, status),
+            Some(msg) => format!("{} exited with {}: {}", self.name, status, msg),
+        };
+        Err(io::Error::other(message))
+    }
+}
+
+impl Read for CommandReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for CommandReader {
+    fn drop(&mut self) {
+        if !self.done {
+            // Stopped reading early, e.g. for -L or -m; don't leave the
+            // child running or unreaped.
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MygrepError;
+
+    fn sh(script: &str) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut command = Command::new("sh");
+        command.args(["-c", script]).stdin(Stdio::null());
+        CommandReader::spawn(&mut command)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_output() {
+        assert_eq!(sh("printf 'a\\nb\\n'").unwrap(), b"a\nb\n");
+        assert_eq!(sh("echo warning >&2; echo out").unwrap(), b"out\n");
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let error = sh("echo x; echo '  ' >&2; echo msg >&2; echo more >&2; exit 3").unwrap_err();
+        assert_eq!(error.to_string(), "sh exited with exit status: 3: msg");
+        let error = sh("exit 4").unwrap_err();
+        assert_eq!(error.to_string(), "sh exited with exit status: 4");
+    }
+
+    #[test]
+    fn test_spawn_error() {
+        let error = CommandReader::spawn(&mut Command::new("/nonexistent"))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            MygrepError::io("f.txt", error).to_string(),
+            "f.txt: /nonexistent: No such file or directory"
+        );
+    }
+}
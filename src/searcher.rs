@@ -0,0 +1,616 @@
+use crate::decompress;
+use crate::encoding;
+use crate::glob::GlobSet;
+use crate::matcher::Matcher;
+use crate::process::CommandReader;
+use crate::sink::{Sink, SinkLine};
+use encoding_rs::Encoding;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+/// How files holding NUL bytes are searched.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BinaryMode {
+    /// Only report that the file matches; its lines are never passed on.
+    #[default]
+    Binary,
+    /// Search it like any other file.
+    Text,
+    /// Treat it as having no matches.
+    WithoutMatch,
+}
+
+/// When files are read through a memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MmapChoice {
+    /// For regular files of at least `MMAP_THRESHOLD` bytes.
+    #[default]
+    Auto,
+    /// For every regular file.
+    Always,
+    Never,
+}
+
+/// How much of the start of the input is checked for NUL bytes up front.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// The size of the chunks plain files are read and searched in.
+const READ_BUFFER_LEN: usize = 64 * 1024;
+
+/// Files at least this large are memory mapped with `MmapChoice::Auto`.
+pub const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// Reads files or other input, splits it into lines and passes the lines
+/// selected by a `Matcher`, with any context around them, to a `Sink`.
+#[derive(Clone, Default)]
+pub struct Searcher {
+    invert_match: bool,
+    binary: BinaryMode,
+    before_context: usize,
+    after_context: usize,
+    mmap: MmapChoice,
+    encoding: Option<&'static Encoding>,
+    ignore_bom: bool,
+    decompress: bool,
+    preprocessor: Option<PathBuf>,
+    preprocessor_glob: Option<Arc<GlobSet>>,
+    null_data: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the lines that do not match instead.
+    pub fn invert_match(mut self, yes: bool) -> Self {
+        self.invert_match = yes;
+        self
+    }
+
+    pub fn binary(mut self, mode: BinaryMode) -> Self {
+        self.binary = mode;
+        self
+    }
+
+    /// Pass on up to `lines` lines before each selected line as context.
+    pub fn before_context(mut self, lines: usize) -> Self {
+        self.before_context = lines;
+        self
+    }
+
+    /// Pass on up to `lines` lines after each selected line as context.
+    pub fn after_context(mut self, lines: usize) -> Self {
+        self.after_context = lines;
+        self
+    }
+
+    pub fn mmap(mut self, choice: MmapChoice) -> Self {
+        self.mmap = choice;
+        self
+    }
+
+    /// Transcode input from `encoding`. Without one, input is only
+    /// transcoded if it starts with a byte order mark.
+    pub fn encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Search input starting with a byte order mark as its raw bytes rather
+    /// than transcoding it, so that byte offsets are those of the input.
+    /// An encoding given with `encoding` is still decoded from.
+    pub fn ignore_bom(mut self, yes: bool) -> Self {
+        self.ignore_bom = yes;
+        self
+    }
+
+    /// Decompress files that start with the magic bytes of a known
+    /// compression format.
+    pub fn decompress(mut self, yes: bool) -> Self {
+        self.decompress = yes;
+        self
+    }
+
+    /// Search the output of `command FILE`, fed FILE on its standard input,
+    /// instead of the files themselves; only for files matching `globs`, if
+    /// given.
+    pub fn preprocessor(mut self, command: Option<PathBuf>, globs: Option<Arc<GlobSet>>) -> Self {
+        self.preprocessor = command;
+        self.preprocessor_glob = globs;
+        self
+    }
+
+    /// Split the input into records ending in a NUL byte instead of lines.
+    /// NUL bytes then no longer mark the input as binary.
+    pub fn null_data(mut self, yes: bool) -> Self {
+        self.null_data = yes;
+        self
+    }
+
+    /// The byte input is split at.
+    fn terminator(&self) -> u8 {
+        if self.null_data { b'\0' } else { b'\n' }
+    }
+
+    /// Searches the file at `path`, which is `relative` to the directory it
+    /// was found under (or `path` itself when it was named directly).
+    /// Preprocessor globs containing a `/` are matched against `relative`.
+    pub fn search_path<M, S>(
+        &self,
+        matcher: &M,
+        path: &Path,
+        relative: &Path,
+        sink: &mut S,
+    ) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let reader = self.open(path, relative)?;
+        self.search_reader(matcher, reader, sink)
+    }
+
+    /// Searches everything `reader` yields.
+    pub fn search_reader<M, R, S>(&self, matcher: &M, reader: R, sink: &mut S) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink + ?Sized,
+    {
+        if self.ignore_bom && self.encoding.is_none() {
+            return Core::new(self, matcher, reader, sink).run();
+        }
+        let reader = encoding::decode(Box::new(reader), self.encoding)?;
+        Core::new(self, matcher, reader, sink).run()
+    }
+
+    /// Searches standard input.
+    pub fn search_stdin<M, S>(&self, matcher: &M, sink: &mut S) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        self.search_reader(matcher, io::stdin().lock(), sink)
+    }
+
+    fn open(&self, path: &Path, relative: &Path) -> io::Result<Box<dyn BufRead>> {
+        let file = fs::File::open(path)?;
+        if let Some(command) = &self.preprocessor
+            && self
+                .preprocessor_glob
+                .as_ref()
+                .is_none_or(|globs| globs.is_file_match(path, relative))
+        {
+            let child = CommandReader::spawn(Command::new(command).arg(path).stdin(file))?;
+            Ok(Box::new(BufReader::new(child)))
+        } else if self.decompress {
+            decompress::open(file, path)
+        } else {
+            Ok(self.open_file(file))
+        }
+    }
+
+    /// Reads a plain file, through a memory map if it is a regular file the
+    /// `MmapChoice` asks for one for.
+    fn open_file(&self, file: fs::File) -> Box<dyn BufRead> {
+        let use_mmap = file.metadata().is_ok_and(|metadata| {
+            metadata.is_file()
+                && metadata.len() > 0
+                && match self.mmap {
+                    MmapChoice::Auto => metadata.len() >= MMAP_THRESHOLD,
+                    MmapChoice::Always => true,
+                    MmapChoice::Never => false,
+                }
+        });
+        if use_mmap {
+            // SAFETY: the map is only read. Like any mmap based reader, a
+            // file truncated while it is searched can still raise SIGBUS.
+            if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+                let _ = map.advise(memmap2::Advice::Sequential);
+                return Box::new(io::Cursor::new(map));
+            }
+        }
+        Box::new(BufReader::with_capacity(READ_BUFFER_LEN, file))
+    }
+}
+
+/// The state of one search.
+struct Core<'s, M: ?Sized, R, S: ?Sized> {
+    searcher: &'s Searcher,
+    matcher: &'s M,
+    reader: R,
+    sink: &'s mut S,
+    /// The line last read, reused so that lines are never allocated.
+    line: Vec<u8>,
+    line_number: u64,
+    /// The offset of the next byte to be read.
+    offset: u64,
+    /// Whether a NUL byte has been seen, either in the leading buffer or in
+    /// the input read so far.
+    binary: bool,
+    /// The last lines that were not passed on, kept as before context.
+    before: VecDeque<(u64, u64, Vec<u8>)>,
+    /// How many more lines to pass on as after context.
+    after: usize,
+    /// The number of the last line passed on.
+    last_line_number: Option<u64>,
+}
+
+impl<'s, M, R, S> Core<'s, M, R, S>
+where
+    M: Matcher + ?Sized,
+    R: BufRead,
+    S: Sink + ?Sized,
+{
+    fn new(searcher: &'s Searcher, matcher: &'s M, reader: R, sink: &'s mut S) -> Self {
+        Self {
+            searcher,
+            matcher,
+            reader,
+            sink,
+            line: Vec::new(),
+            line_number: 0,
+            offset: 0,
+            binary: false,
+            before: VecDeque::new(),
+            after: 0,
+            last_line_number: None,
+        }
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        // Only sniff the first block: a memory mapped file is buffered whole.
+        let buf = self.reader.fill_buf()?;
+        self.binary = !self.searcher.null_data
+            && memchr::memchr(0, &buf[..buf.len().min(BINARY_SNIFF_LEN)]).is_some();
+        let skip = !self.searcher.invert_match && self.matcher.can_search_buffer();
+        loop {
+            if skip && self.after == 0 {
+                self.skip_to_candidate()?;
+            }
+            let offset = self.offset;
+            if !self.read_line()? {
+                return Ok(());
+            }
+            // With null data, the only NUL was the terminator taken off.
+            if !self.binary && memchr::memchr(0, &self.line).is_some() {
+                self.binary = true;
+            }
+            let quiet = self.binary && self.searcher.binary == BinaryMode::Binary;
+            if self.binary && self.searcher.binary == BinaryMode::WithoutMatch {
+                return self.sink.binary_skipped(self.line_number);
+            }
+            let selected = self.matcher.is_match(&self.line) != self.searcher.invert_match;
+            let go_on = if selected && quiet {
+                self.sink.binary_matched(self.line_number)?
+            } else if selected {
+                self.after = self.searcher.after_context;
+                self.pass_before()? && self.pass_line(offset, true)?
+            } else if self.after > 0 {
+                self.after -= 1;
+                quiet || self.pass_line(offset, false)?
+            } else {
+                self.keep_before(offset);
+                true
+            };
+            if !go_on {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads the next line into `line` as raw bytes, without its line
+    /// terminator. Returns `false` at the end of the input.
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        let n = loop {
+            match self
+                .reader
+                .read_until(self.searcher.terminator(), &mut self.line)
+            {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res?,
+            }
+        };
+        if n == 0 {
+            return Ok(false);
+        }
+        self.offset += n as u64;
+        if self.line.last() == Some(&self.searcher.terminator()) {
+            self.line.pop();
+            if !self.searcher.null_data && self.line.last() == Some(&b'\r') {
+                self.line.pop();
+            }
+        }
+        self.line_number += 1;
+        Ok(true)
+    }
+
+    /// Skips the whole lines at the front of the buffered input that cannot
+    /// be selected, by searching the buffer for a match rather than each
+    /// line, keeping only the lines that may be needed as before context.
+    /// The line holding a hit, or a line the buffer ends in the middle of,
+    /// is left to be read and matched line by line. For a memory mapped
+    /// file the buffer is the rest of the file.
+    fn skip_to_candidate(&mut self) -> io::Result<()> {
+        let buf = self.reader.fill_buf()?;
+        let end = self.matcher.find(buf).map_or(buf.len(), |m| m.start);
+        let terminator = self.searcher.terminator();
+        let skip = memchr::memrchr_iter(terminator, &buf[..end])
+            .nth(self.searcher.before_context)
+            .map_or(0, |i| i + 1);
+        if skip == 0 {
+            return Ok(());
+        }
+        let skipped = &buf[..skip];
+        if !self.binary && !self.searcher.null_data && memchr::memchr(0, skipped).is_some() {
+            self.binary = true;
+        }
+        self.line_number += memchr::memchr_iter(terminator, skipped).count() as u64;
+        self.offset += skip as u64;
+        self.before.clear();
+        self.reader.consume(skip);
+        Ok(())
+    }
+
+    /// Remembers the current line as possible before context.
+    fn keep_before(&mut self, offset: u64) {
+        if self.searcher.before_context == 0 {
+            return;
+        }
+        let mut line = if self.before.len() == self.searcher.before_context {
+            self.before
+                .pop_front()
+                .map(|(_, _, line)| line)
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        line.clear();
+        line.extend_from_slice(&self.line);
+        self.before.push_back((self.line_number, offset, line));
+    }
+
+    fn pass_before(&mut self) -> io::Result<bool> {
+        while let Some((line_number, byte_offset, bytes)) = self.before.pop_front() {
+            let line = SinkLine {
+                line_number,
+                byte_offset,
+                bytes: &bytes,
+            };
+            if !self.pass_break(line_number)? || !self.sink.context(&line)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Passes on the current line, either as selected or as context.
+    fn pass_line(&mut self, offset: u64, selected: bool) -> io::Result<bool> {
+        if !self.pass_break(self.line_number)? {
+            return Ok(false);
+        }
+        let line = SinkLine {
+            line_number: self.line_number,
+            byte_offset: offset,
+            bytes: &self.line,
+        };
+        if selected {
+            self.sink.matched(&line)
+        } else {
+            self.sink.context(&line)
+        }
+    }
+
+    /// Tells the sink about a gap before `line_number`, if context is shown.
+    fn pass_break(&mut self, line_number: u64) -> io::Result<bool> {
+        let last = self.last_line_number.replace(line_number);
+        let has_context = self.searcher.before_context > 0 || self.searcher.after_context > 0;
+        if has_context && last.is_some_and(|last| last + 1 < line_number) {
+            return self.sink.context_break();
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::SubstringFinder;
+
+    /// Records everything it is given as `kind:line_number:offset:line`.
+    #[derive(Default)]
+    struct Collect {
+        lines: Vec<String>,
+        stop_after: Option<usize>,
+    }
+
+    impl Collect {
+        fn push(&mut self, kind: &str, line: &SinkLine<'_>) -> io::Result<bool> {
+            self.lines.push(format!(
+                "{}:{}:{}:{}",
+                kind,
+                line.line_number,
+                line.byte_offset,
+                String::from_utf8_lossy(line.bytes)
+            ));
+            Ok(self.stop_after.is_none_or(|n| self.lines.len() < n))
+        }
+    }
+
+    impl Sink for Collect {
+        fn matched(&mut self, line: &SinkLine<'_>) -> io::Result<bool> {
+            self.push("m", line)
+        }
+
+        fn context(&mut self, line: &SinkLine<'_>) -> io::Result<bool> {
+            self.push("c", line)
+        }
+
+        fn context_break(&mut self) -> io::Result<bool> {
+            self.lines.push(String::from("--"));
+            Ok(true)
+        }
+
+        fn binary_matched(&mut self, line_number: u64) -> io::Result<bool> {
+            self.lines.push(format!("binary:{}", line_number));
+            Ok(false)
+        }
+
+        fn binary_skipped(&mut self, line_number: u64) -> io::Result<()> {
+            self.lines.push(format!("skipped:{}", line_number));
+            Ok(())
+        }
+    }
+
+    fn search(searcher: &Searcher, pattern: &str, input: &[u8]) -> Vec<String> {
+        let mut sink = Collect::default();
+        let matcher = SubstringFinder::new(pattern, false);
+        searcher.search_reader(&matcher, input, &mut sink).unwrap();
+        sink.lines
+    }
+
+    #[test]
+    fn test_matches() {
+        let input = b"one\ntwo\r\nthree\nfour";
+        assert_eq!(
+            search(&Searcher::new(), "o", input),
+            ["m:1:0:one", "m:2:4:two", "m:4:15:four"]
+        );
+        assert_eq!(
+            search(&Searcher::new().invert_match(true), "o", input),
+            ["m:3:9:three"]
+        );
+        assert!(search(&Searcher::new(), "five", input).is_empty());
+    }
+
+    #[test]
+    fn test_byte_offsets_in_file() {
+        // Offsets count bytes, not characters, and carry on across reads.
+        let mut input = "é\r\n".repeat(READ_BUFFER_LEN / 4).into_bytes();
+        let offset = input.len();
+        input.extend_from_slice("ünë\nx\n".as_bytes());
+        let path = std::env::temp_dir().join(format!("mygrep-offsets-{}", std::process::id()));
+        fs::write(&path, &input).unwrap();
+        let matcher = SubstringFinder::new("n", false);
+        for choice in [MmapChoice::Never, MmapChoice::Always] {
+            let mut sink = Collect::default();
+            let searcher = Searcher::new().mmap(choice);
+            searcher
+                .search_path(&matcher, &path, &path, &mut sink)
+                .unwrap();
+            let line_number = READ_BUFFER_LEN / 4 + 1;
+            assert_eq!(sink.lines, [format!("m:{}:{}:ünë", line_number, offset)]);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_preprocessor() {
+        let dir = std::env::temp_dir().join(format!("mygrep-pre-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let upcase = dir.join("upcase");
+        fs::write(&upcase, "#!/bin/sh\ntr a-z A-Z\n").unwrap();
+        fs::set_permissions(&upcase, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        let (txt, log) = (dir.join("a.txt"), dir.join("b.log"));
+        fs::write(&txt, "plain\n").unwrap();
+        fs::write(&log, "plain\n").unwrap();
+        let search = |searcher: &Searcher, pattern: &str, path: &Path| {
+            let mut sink = Collect::default();
+            let matcher = SubstringFinder::new(pattern, false);
+            searcher
+                .search_path(&matcher, path, path, &mut sink)
+                .unwrap();
+            sink.lines
+        };
+        let searcher = Searcher::new().preprocessor(Some(upcase.clone()), None);
+        assert_eq!(search(&searcher, "PLAIN", &txt), ["m:1:0:PLAIN"]);
+        assert_eq!(search(&searcher, "PLAIN", &log), ["m:1:0:PLAIN"]);
+        let globs = Arc::new(GlobSet::new(&["*.txt"]).unwrap());
+        let searcher = Searcher::new().preprocessor(Some(upcase), Some(globs));
+        assert_eq!(search(&searcher, "PLAIN", &txt), ["m:1:0:PLAIN"]);
+        assert!(search(&searcher, "PLAIN", &log).is_empty());
+        assert_eq!(search(&searcher, "plain", &log), ["m:1:0:plain"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_byte_offsets_after_bom() {
+        let input = b"\xef\xbb\xbfab\ncd\n";
+        assert_eq!(search(&Searcher::new(), "cd", input), ["m:2:3:cd"]);
+        let searcher = Searcher::new().ignore_bom(true);
+        assert_eq!(search(&searcher, "cd", input), ["m:2:6:cd"]);
+        let input = b"\xff\xfea\0b\0\n\0c\0d\0\n\0";
+        assert_eq!(search(&Searcher::new(), "cd", input), ["m:2:3:cd"]);
+        let searcher = Searcher::new().ignore_bom(true).binary(BinaryMode::Text);
+        assert!(search(&searcher, "cd", input).is_empty());
+        assert_eq!(search(&searcher, "c\0d", input), ["m:2:7:\0c\0d\0"]);
+    }
+
+    #[test]
+    fn test_sink_stops_search() {
+        let mut sink = Collect {
+            stop_after: Some(1),
+            ..Default::default()
+        };
+        let matcher = SubstringFinder::new("a", false);
+        Searcher::new()
+            .search_reader(&matcher, &b"a\nb\na\n"[..], &mut sink)
+            .unwrap();
+        assert_eq!(sink.lines, ["m:1:0:a"]);
+    }
+
+    #[test]
+    fn test_context() {
+        let input = b"1\n2\nx3\n4\n5\n6\n7\nx8\nx9\n10\n";
+        let searcher = Searcher::new().before_context(1).after_context(1);
+        assert_eq!(
+            search(&searcher, "x", input),
+            [
+                "c:2:2:2",
+                "m:3:4:x3",
+                "c:4:7:4",
+                "--",
+                "c:7:13:7",
+                "m:8:15:x8",
+                "m:9:18:x9",
+                "c:10:21:10"
+            ]
+        );
+        let searcher = Searcher::new().before_context(3);
+        assert_eq!(
+            search(&searcher, "x8", input),
+            ["c:5:9:5", "c:6:11:6", "c:7:13:7", "m:8:15:x8"]
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        let input = b"a\n\0\na\n";
+        assert_eq!(search(&Searcher::new(), "a", input), ["binary:1"]);
+        let searcher = Searcher::new().binary(BinaryMode::Text);
+        assert_eq!(search(&searcher, "a", input), ["m:1:0:a", "m:3:4:a"]);
+        let searcher = Searcher::new().binary(BinaryMode::WithoutMatch);
+        assert_eq!(search(&searcher, "a", input), ["skipped:1"]);
+    }
+
+    #[test]
+    fn test_null_data() {
+        let input = b"one\ntwo\0three\0four\nfive";
+        let searcher = Searcher::new().null_data(true);
+        assert_eq!(
+            search(&searcher, "o", input),
+            ["m:1:0:one\ntwo", "m:3:14:four\nfive"]
+        );
+        let searcher = searcher.before_context(1);
+        assert_eq!(
+            search(&searcher, "five", input),
+            ["c:2:8:three", "m:3:14:four\nfive"]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+
+/// A line handed to a `Sink`, without its line terminator.
+#[derive(Debug, Clone, Copy)]
+pub struct SinkLine<'b> {
+    /// The 1-based number of the line.
+    pub line_number: u64,
+    /// The offset of the start of the line from the start of the input, as
+    /// searched: after decompressing, preprocessing or transcoding it.
+    pub byte_offset: u64,
+    pub bytes: &'b [u8],
+}
+
+/// Receives the results of a search as the `Searcher` finds them. Every
+/// method returns whether the search should go on.
+pub trait Sink {
+    /// Called with every selected line.
+    fn matched(&mut self, line: &SinkLine<'_>) -> io::Result<bool>;
+
+    /// Called with each line of context around a selected line.
+    fn context(&mut self, _line: &SinkLine<'_>) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called between two groups of selected and context lines that are not
+    /// adjacent, when context was asked for.
+    fn context_break(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called instead of `matched` for a selected line of a binary file,
+    /// whose lines are never passed on. By default the search stops at the
+    /// first one.
+    fn binary_matched(&mut self, _line_number: u64) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Called when the search stops at the line `line_number` because it
+    /// holds binary data and binary files are skipped.
+    fn binary_skipped(&mut self, _line_number: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -37,7 +37,7 @@ where
                         }
                         i += 1;
                     }
-                    if q.len() > 0 && w.len() < *c {
+                    if !q.is_empty() && w.len() < *c {
                         let free_count = (*c - w.len()).min(q.len());
                         for _ in 0..free_count {
                             let job = q.pop_front().unwrap();
@@ -48,11 +48,10 @@ where
                     let end = quit.lock().map(|q| *q).unwrap_or(true);
                     if end {
                         loop {
-                            while w.len() > 0 {
-                                let h = w.pop().unwrap();
+                            while let Some(h) = w.pop() {
                                 let _ = h.join();
                             }
-                            if q.len() == 0 {
+                            if q.is_empty() {
                                 break;
                             }
                             for _ in 0..q.len().min(*c) {
@@ -67,12 +66,11 @@ where
                 thread::sleep(std::time::Duration::from_micros(10_000));
             }
         });
-        let this = Self {
+        Self {
             queue: queue_clone,
             manager: handle,
             quit: quit_clone,
-        };
-        return this;
+        }
     }
 
     pub fn join(self) {
@@ -88,6 +86,5 @@ where
         guard
             .map(|mut q| q.push_back(f))
             .expect("something went wrong while accessing ThreadPool queue");
-        return;
     }
 }
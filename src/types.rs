@@ -0,0 +1,141 @@
+use crate::error::MygrepError;
+use crate::glob::GlobSet;
+use std::collections::BTreeMap;
+
+/// The file types known without `--type-add`, and the globs naming them.
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Named sets of globs, such as `rust` for `*.rs`, selecting files by type.
+#[derive(Debug, Clone)]
+pub struct Types {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Types {
+    fn default() -> Self {
+        let types = DEFAULT_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|g| g.to_string()).collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+        Types { types }
+    }
+}
+
+impl Types {
+    /// The built-in types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the glob of `spec`, of the form `NAME:GLOB`, to the type `NAME`,
+    /// defining the type if it is new.
+    pub fn add(&mut self, spec: &str) -> Result<(), MygrepError> {
+        let (name, glob) = match spec.split_once(':') {
+            Some((name, glob)) if !name.is_empty() && !glob.is_empty() => (name, glob),
+            _ => {
+                return Err(MygrepError::Config(format!(
+                    "invalid type definition '{}': expected NAME:GLOB",
+                    spec
+                )));
+            }
+        };
+        // Reject a bad glob here rather than when the type is first used.
+        GlobSet::new(&[glob])?;
+        self.types
+            .entry(name.to_string())
+            .or_default()
+            .push(glob.to_string());
+        Ok(())
+    }
+
+    /// The globs of the type `name`.
+    pub fn globs(&self, name: &str) -> Result<&[String], MygrepError> {
+        self.types
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| MygrepError::Config(format!("unknown file type '{}'", name)))
+    }
+
+    /// Compiles the type `name` for matching.
+    pub fn file_type(&self, name: &str) -> Result<FileType, MygrepError> {
+        Ok(FileType {
+            name: name.to_string(),
+            globs: GlobSet::new(self.globs(name)?)?,
+        })
+    }
+
+    /// Every type with its globs, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.types
+            .iter()
+            .map(|(name, globs)| (name.as_str(), globs.as_slice()))
+    }
+}
+
+/// A type compiled for matching file names against.
+pub struct FileType {
+    name: String,
+    globs: GlobSet,
+}
+
+impl FileType {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn globs(&self) -> &GlobSet {
+        &self.globs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_types() {
+        let mut types = Types::new();
+        assert_eq!(types.globs("rust").unwrap(), ["*.rs"]);
+        assert!(types.globs("proto").is_err());
+        types.add("proto:*.proto").unwrap();
+        types.add("rust:*.rs.in").unwrap();
+        assert_eq!(types.globs("proto").unwrap(), ["*.proto"]);
+        assert_eq!(types.globs("rust").unwrap(), ["*.rs", "*.rs.in"]);
+        assert!(types.add("proto").is_err());
+        assert!(types.add(":*.proto").is_err());
+        assert!(types.add("proto:*.[z-a]").is_err());
+        let names: Vec<_> = types.iter().map(|(name, _)| name).collect();
+        assert!(names.is_sorted());
+    }
+
+    #[test]
+    fn test_file_type() {
+        let rust = Types::new().file_type("rust").unwrap();
+        assert_eq!(rust.name(), "rust");
+        let path = Path::new("src/main.rs");
+        assert_eq!(rust.globs().file_match(path, path), Some("*.rs"));
+        assert!(Types::new().file_type("no-such-type").is_err());
+    }
+}